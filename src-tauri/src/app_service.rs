@@ -1,34 +1,195 @@
 use anyhow::{Context, Result};
+use futures::{
+    future::{join_all, BoxFuture},
+    StreamExt,
+};
 use librqbit::{Session, SessionOptions, SessionPersistenceConfig};
-use serde::Serialize;
-use std::{path::PathBuf, sync::Arc, vec};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashSet, path::PathBuf, pin::pin, sync::Arc, time::Duration, vec};
 use tokio::{
     fs::create_dir,
     sync::{watch::Receiver, Mutex},
 };
+use url::Url;
 
 use crate::{
-    library::{Library, LibraryEntry, LibraryEntrySettings},
+    download_rule::{DownloadRule, DownloadRules, RuleMatch},
+    favourite_uploader::{FavouriteUploader, FavouriteUploaders},
+    http::HttpClient,
+    library::{
+        entry_dir_name, ImportMode, InvalidArchive, Library, LibraryEntry, LibraryEntrySettings,
+    },
     metadata::{mangabaka::Mangabaka, Metadata, MetadataProvider},
     metafile::Metafile,
     reader::{cbz_reader::CBZReader, Reader},
-    source::{nyaa::Nyaa, MediaInfo, PaginationInfo, Source, SourceMeta},
-    torrent::{rqbit_service::RqbitService, TorrentService, TorrentStats},
+    saved_search::{NewReleases, SavedSearch, SavedSearches},
+    search_group::{group_results, GroupedSearchResponse},
+    settings::{AppSettings, BandwidthSettings, NyaaSettings, SeedingSettings},
+    source::{
+        cache::ResponseCache, nyaa::Nyaa, registry::SourceRegistry, release::ParsedRelease,
+        MediaInfo, PaginationInfo, SearchQuery, SourceMeta, Sources, TorrentDetails,
+    },
+    torrent::{
+        queue::DownloadQueue, rqbit_service::RqbitService, BandwidthLimits, BrokenTorrent,
+        SeedingPolicy, TorrentFile, TorrentService, TorrentStats,
+    },
 };
 
+/// Upper bound for the pages a single `search_all` walks, so one query can't hammer a source
+pub const MAX_SEARCH_PAGES: u32 = 20;
+
 pub struct AppService {
-    source: Nyaa,
+    sources: Arc<SourceRegistry>,
     base_dir: PathBuf,
-    pub torrent_service: Arc<Mutex<dyn TorrentService>>,
-    pub metadata_provider: Mangabaka,
+    pub torrent_service: Arc<Mutex<DownloadQueue>>,
+    pub metadata_provider: Arc<Mangabaka>,
     library: Library,
     cbz_reader: CBZReader,
+    saved_searches: SavedSearches,
+    download_rules: DownloadRules,
+    favourite_uploaders: FavouriteUploaders,
+    settings: AppSettings,
+}
+
+/// Searches sources and attaches metadata to the results.
+/// Cheap to clone, it shares the sources and metadata provider with `AppService`.
+#[derive(Clone)]
+pub struct Searcher {
+    sources: Arc<SourceRegistry>,
+    metadata_provider: Arc<Mangabaka>,
+}
+
+impl Searcher {
+    /// Walk up to `max_pages` pages of `query`, handing each page to `on_page` as it arrives
+    pub async fn search_all(
+        &self,
+        query: SearchQuery,
+        max_pages: u32,
+        mut on_page: impl FnMut(SearchResponse) -> Result<()>,
+    ) -> Result<()> {
+        let max_pages = max_pages.min(MAX_SEARCH_PAGES);
+        let mut pages = pin!(self.sources.search_pages(&query, max_pages));
+
+        while let Some(page) = pages.next().await {
+            let (media_info, pagination) = page?;
+            on_page(SearchResponse {
+                search_results: self.to_search_results(media_info).await?,
+                pagination,
+            })?;
+        }
+
+        Ok(())
+    }
+
+    async fn to_search_results(
+        &self,
+        media_info: Vec<(Sources, MediaInfo)>,
+    ) -> Result<Vec<SearchResult>> {
+        let mut results = vec![];
+
+        let mut metadata_hits = 0;
+
+        for (provider, media) in media_info {
+            let normalized_title = self.sources.get(provider)?.normalize_title(&media.title);
+            let metadata = self
+                .metadata_provider
+                .fetch_metadata(&normalized_title)
+                .await
+                .map_err(|err| {
+                    log::warn!(
+                        "No metdata found for \"{}\": {}",
+                        media.title,
+                        err.to_string()
+                    );
+                    err
+                })
+                .ok();
+
+            if metadata.is_some() {
+                metadata_hits += 1;
+            }
+
+            results.push(SearchResult {
+                provider,
+                release: ParsedRelease::parse(&media.title),
+                media_info: media,
+                metadata,
+            });
+        }
+
+        log::debug!(
+            "Metadata hit rate: {}/{} = {:.2}%",
+            metadata_hits,
+            results.len(),
+            (metadata_hits as f64 / results.len() as f64 * 100.0)
+        );
+
+        Ok(results)
+    }
+
+    /// Latest releases from each of `uploaders` that match `query`, newest first.
+    /// Uploaders whose listing fails to load are skipped.
+    pub async fn uploader_feed(
+        &self,
+        uploaders: &[FavouriteUploader],
+        query: SearchQuery,
+    ) -> Result<Vec<SearchResult>> {
+        let queries: Vec<SearchQuery> = uploaders
+            .iter()
+            .map(|uploader| SearchQuery {
+                uploader: Some(uploader.name.clone()),
+                source: Some(uploader.provider),
+                page: None,
+                ..query.clone()
+            })
+            .collect();
+        let listings = queries.iter().map(|query| self.sources.search(query));
+
+        let mut releases = vec![];
+        for (uploader, listing) in uploaders.iter().zip(join_all(listings).await) {
+            match listing {
+                Ok((results, _)) => releases.extend(results),
+                Err(err) => log::warn!("Failed to fetch uploads by {}: {}", uploader.name, err),
+            }
+        }
+
+        releases.sort_by_key(|(_, info)| std::cmp::Reverse(info.timestamp));
+        let mut seen = HashSet::new();
+        releases.retain(|(provider, info)| seen.insert((*provider, info.id.clone())));
+
+        self.to_search_results(releases).await
+    }
+}
+
+/// Starts downloads without holding `AppService`, resolving a torrent can take a while.
+/// Cheap to clone, it shares the sources with `AppService`.
+#[derive(Clone)]
+pub struct Downloader {
+    sources: Arc<SourceRegistry>,
+    library_dir: PathBuf,
+}
+
+impl Downloader {
+    /// Start downloading `source` into the library, returning the folder it downloads into.
+    /// Only the files at the `only_files` indices are downloaded when it is set.
+    pub async fn download(
+        &self,
+        source: &SourceMeta,
+        only_files: Option<Vec<usize>>,
+    ) -> Result<PathBuf> {
+        self.sources
+            .get(source.provider)?
+            .download(&source.id, &self.library_dir, only_files)
+            .await
+    }
 }
 
 #[derive(Serialize)]
 pub struct SearchResult {
-    media_info: MediaInfo,
-    metadata: Option<Metadata>,
+    pub provider: Sources,
+    pub release: ParsedRelease,
+    pub media_info: MediaInfo,
+    pub metadata: Option<Metadata>,
 }
 
 #[derive(Serialize)]
@@ -37,6 +198,26 @@ pub struct SearchResponse {
     pagination: PaginationInfo,
 }
 
+/// The library entry of a download once it has finished
+#[derive(Serialize, Clone)]
+pub struct FinishedDownload {
+    pub entry: LibraryEntry,
+    pub invalid_archives: Vec<InvalidArchive>,
+}
+
+/// What to do with a torrent that could not be restored
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type")]
+pub enum RepairAction {
+    /// Attach the torrent to the library entry `source_id` and download into its folder
+    Relink { source_id: String },
+    /// Make a new library entry for the torrent, in its old folder when that is known.
+    /// The entry is local content unless the torrent's comment links to its upload.
+    Recreate,
+    /// Remove the torrent from the session, leaving its files in place
+    Forget,
+}
+
 impl AppService {
     pub async fn new(app_data_dir: PathBuf) -> Result<Self> {
         log::info!("Initializing app service");
@@ -60,23 +241,155 @@ impl AppService {
         )
         .await
         .unwrap();
-        let client = reqwest::Client::new();
+        let settings = AppSettings::read(&app_data_dir).await?;
+        let client = HttpClient::new(reqwest::Client::new()).with_settings(settings.http.clone());
         let torrent_service = Arc::new(Mutex::new(
-            RqbitService::new(
-                session,
-                client.clone(),
-                &session_persistence_path.join("session.json"),
-            )
-            .await,
+            DownloadQueue::new(Box::new(
+                RqbitService::new(
+                    session,
+                    client.clone(),
+                    &session_persistence_path.join("session.json"),
+                )
+                .await
+                .with_magnet_timeout(Duration::from_secs(settings.torrent.magnet_timeout_secs))
+                .with_torrent_limits(
+                    settings
+                        .bandwidth
+                        .torrents
+                        .iter()
+                        .map(|(id, limits)| (id.clone(), limits.clamped()))
+                        .collect(),
+                )
+                .await,
+            ))
+            .with_max_active(settings.queue.max_active_downloads)
+            .with_saved_queue(&app_data_dir)
+            .await?,
+        ));
+        torrent_service
+            .lock()
+            .await
+            .set_global_limits(settings.bandwidth.global.clamped());
+        AppService::apply_seeding_policies(&mut *torrent_service.lock().await, &settings);
+
+        let cache = Arc::new(ResponseCache::new(
+            Duration::from_secs(settings.cache.ttl_secs),
+            settings.cache.max_entries,
         ));
 
         Ok(AppService {
-            source: Nyaa::new(torrent_service.clone(), client.clone()),
-            metadata_provider: Mangabaka::setup(&client, &app_data_dir.join("db")).await?,
-            base_dir: app_data_dir,
+            sources: Arc::new(
+                SourceRegistry::new().with_source(Box::new(AppService::setup_nyaa(
+                    Nyaa::new(torrent_service.clone(), client.clone())
+                        .with_cache(cache)
+                        .with_view_ttl(Duration::from_secs(settings.cache.view_ttl_secs)),
+                    &settings.nyaa,
+                ))),
+            ),
+            metadata_provider: Arc::new(Mangabaka::setup(&client, &app_data_dir.join("db")).await?),
             torrent_service,
             library,
             cbz_reader: CBZReader::new(),
+            saved_searches: SavedSearches::read(&app_data_dir).await?,
+            download_rules: DownloadRules::read(&app_data_dir).await?,
+            favourite_uploaders: FavouriteUploaders::read(&app_data_dir).await?,
+            base_dir: app_data_dir,
+            settings,
+        })
+    }
+
+    fn setup_nyaa(nyaa: Nyaa, settings: &NyaaSettings) -> Nyaa {
+        let parse_url = |url: &String| {
+            Url::parse(url)
+                .map_err(|err| log::warn!("Ignoring invalid nyaa url {}: {}", url, err))
+                .ok()
+        };
+
+        let nyaa = match parse_url(&settings.base_url) {
+            Some(base_url) => nyaa.with_base_url(base_url),
+            None => nyaa,
+        };
+
+        nyaa.with_mirrors(settings.mirrors.iter().filter_map(parse_url).collect())
+            .with_backend(settings.backend)
+    }
+
+    fn apply_seeding_policies(torrent_service: &mut impl TorrentService, settings: &AppSettings) {
+        torrent_service.set_global_seeding_policy(settings.seeding.global);
+        for (id, policy) in &settings.seeding.torrents {
+            torrent_service.set_torrent_seeding_policy(id, Some(*policy));
+        }
+    }
+
+    pub async fn wait_until_finished(&self, id: &str) -> Result<BoxFuture<'static, Result<()>>> {
+        self.torrent_service.lock().await.wait_until_finished(id)
+    }
+
+    /// Ids of downloads that are still in progress
+    pub async fn unfinished_downloads(&self) -> Vec<String> {
+        self.torrent_service
+            .lock()
+            .await
+            .list_torrents()
+            .into_iter()
+            .filter(|stats| !stats.is_finished())
+            .map(|stats| stats.id().to_owned())
+            .collect()
+    }
+
+    /// Pick up the files of the finished download `id`, returning its folder and the files it
+    /// wrote, to be checked with `Library::validate_archives` before `complete_download`.
+    /// Other files in the folder were checked when their own download finished.
+    pub async fn refresh_download_files(&mut self, id: &str) -> Result<(PathBuf, Vec<String>)> {
+        log::info!("Completing download {}", id);
+        let new_files = self.library.refresh_files(id).await?;
+        let files = match self.torrent_service.lock().await.get_files(id) {
+            Ok(files) => files
+                .into_iter()
+                .filter(|file| file.selected)
+                .map(|file| file.path)
+                .collect(),
+            Err(err) => {
+                log::warn!("No files for download {}, checking new ones: {}", id, err);
+                new_files
+            }
+        };
+
+        let entry = self
+            .library
+            .get_entry(id)
+            .await
+            .context(format!("Missing library entry for {}", id))?;
+        Ok((entry.output_dir, files))
+    }
+
+    /// Bring the library entry up to date once the download `id` finishes,
+    /// looking the metadata up again if it was missing
+    pub async fn complete_download(
+        &mut self,
+        id: &str,
+        invalid_archives: Vec<InvalidArchive>,
+    ) -> Result<FinishedDownload> {
+        let entry = self
+            .library
+            .get_entry(id)
+            .await
+            .context(format!("Missing library entry for {}", id))?;
+        // local content has no source to look it up on
+        if entry.metafile.metadata.is_none() && entry.metafile.source.provider != Sources::Local {
+            match self.get_metadata_by_id(&entry.metafile.source).await {
+                Ok(metadata) => self.library.update_metadata(id, Some(metadata)).await?,
+                Err(err) => log::warn!("Still no metadata for {}: {}", id, err),
+            }
+        }
+
+        Ok(FinishedDownload {
+            entry: self
+                .library
+                .get_entry(id)
+                .await
+                .context(format!("Missing library entry for {}", id))?,
+            invalid_archives,
         })
     }
 
@@ -88,18 +401,18 @@ impl AppService {
             .context("Receiver does not exist")
     }
 
-    pub async fn download(&mut self, id: &str) -> Result<()> {
-        let library_dir = self.base_dir.join("library");
-        let output_dir = self.source.download(id, &library_dir).await?;
-        let metafile = Metafile::new(
-            SourceMeta {
-                id: id.to_owned(),
-                provider: self.source.get_variant(),
-            },
-            self.get_metadata_by_id(id).await.ok(),
-        );
+    pub fn downloader(&self) -> Downloader {
+        Downloader {
+            sources: self.sources.clone(),
+            library_dir: self.base_dir.join("library"),
+        }
+    }
 
-        log::debug!("Writing metafile for {}", id);
+    /// Add the download of `source`, started by a `Downloader` in `output_dir`, to the library
+    pub async fn add_download(&mut self, source: SourceMeta, output_dir: PathBuf) -> Result<()> {
+        let metadata = self.get_metadata_by_id(&source).await.ok();
+        log::debug!("Writing metafile for {}", source.id);
+        let metafile = Metafile::new(source, metadata);
         metafile.write(&output_dir).await?;
 
         self.library.add_entry(metafile, output_dir).await?;
@@ -107,68 +420,106 @@ impl AppService {
         Ok(())
     }
 
-    pub async fn get_title_by_id(&self, id: &str) -> Result<String> {
-        self.source.get_info_by_id(id).await.map(|info| info.title)
+    /// Files in `source` that can be picked before downloading it
+    pub async fn list_source_files(&self, source: &SourceMeta) -> Result<Vec<TorrentFile>> {
+        log::info!("Listing files for {}", source.id);
+        self.sources
+            .get(source.provider)?
+            .list_files(&source.id)
+            .await
     }
 
-    pub async fn list_torrents(&self) -> Vec<TorrentStats> {
-        log::debug!("Listing torrents");
-        self.torrent_service.lock().await.list_torrents()
+    pub async fn get_download_files(&self, id: &str) -> Result<Vec<TorrentFile>> {
+        self.torrent_service.lock().await.get_files(id)
     }
 
-    pub async fn search(&self, query: String) -> Result<SearchResponse> {
-        let (media_info, pagination) = self.source.search(&query).await?;
-        let mut results = vec![];
+    /// Only download the files at the `only_files` indices of the active download `id`.
+    /// Files that are no longer selected are deleted.
+    pub async fn update_download_files(&mut self, id: &str, only_files: Vec<usize>) -> Result<()> {
+        let mut torrent_service = self.torrent_service.lock().await;
+        let deselected: Vec<String> = torrent_service
+            .get_files(id)?
+            .into_iter()
+            .filter(|file| !only_files.contains(&file.index))
+            .map(|file| file.path)
+            .collect();
 
-        let mut metadata_hits = 0;
+        torrent_service.update_files(id, only_files).await?;
+        drop(torrent_service);
 
-        for media in media_info {
-            let normalized_title = self.source.normalize_title(&media.title);
-            let metadata = self
-                .metadata_provider
-                .fetch_metadata(&normalized_title)
-                .await
-                .map_err(|err| {
-                    log::warn!(
-                        "No metdata found for \"{}\": {}",
-                        media.title,
-                        err.to_string()
-                    );
-                    err
-                })
-                .ok();
+        self.library.remove_files(id, &deselected).await
+    }
 
-            if metadata.is_some() {
-                metadata_hits += 1;
-            }
+    pub async fn get_title_by_id(&self, source: &SourceMeta) -> Result<String> {
+        self.sources
+            .get(source.provider)?
+            .get_info_by_id(&source.id)
+            .await
+            .map(|info| info.title)
+    }
 
-            results.push(SearchResult {
-                media_info: media,
-                metadata,
-            });
-        }
+    pub async fn get_torrent_details(&self, source: &SourceMeta) -> Result<TorrentDetails> {
+        log::info!("Fetching details for {}", source.id);
+        self.sources
+            .get(source.provider)?
+            .get_details_by_id(&source.id)
+            .await
+    }
 
-        log::debug!(
-            "Metadata hit rate: {}/{} = {:.2}%",
-            metadata_hits,
-            results.len(),
-            (metadata_hits as f64 / results.len() as f64 * 100.0)
-        );
+    pub async fn list_torrents(&self) -> Vec<TorrentStats> {
+        log::debug!("Listing torrents");
+        self.torrent_service.lock().await.list_torrents()
+    }
 
+    pub async fn search(&self, query: SearchQuery) -> Result<SearchResponse> {
+        let (media_info, pagination) = self.sources.search(&query).await?;
         Ok(SearchResponse {
-            search_results: results,
+            search_results: self.to_search_results(media_info).await?,
             pagination,
         })
     }
 
+    /// Same as `search`, with releases of the same series grouped together
+    pub async fn search_grouped(&self, query: SearchQuery) -> Result<GroupedSearchResponse> {
+        let SearchResponse {
+            search_results,
+            pagination,
+        } = self.search(query).await?;
+
+        let groups = group_results(search_results, |result| {
+            match self.sources.get(result.provider) {
+                Ok(source) => source.normalize_title(&result.media_info.title),
+                Err(_) => result.release.title.to_lowercase(),
+            }
+        });
+
+        Ok(GroupedSearchResponse { groups, pagination })
+    }
+
+    /// Everything needed to search, so long searches can run without holding the service
+    pub fn searcher(&self) -> Searcher {
+        Searcher {
+            sources: self.sources.clone(),
+            metadata_provider: self.metadata_provider.clone(),
+        }
+    }
+
+    async fn to_search_results(
+        &self,
+        media_info: Vec<(Sources, MediaInfo)>,
+    ) -> Result<Vec<SearchResult>> {
+        self.searcher().to_search_results(media_info).await
+    }
+
     pub async fn toggle_pause(&self, id: &str) -> Result<()> {
         log::info!("Pausing download for {}", id);
         self.torrent_service.lock().await.toggle_pause(id).await
     }
 
-    async fn get_metadata_by_id(&self, id: &str) -> Result<Metadata> {
-        let info = self.source.get_info_by_id(id).await?;
-        let normalized_title = self.source.normalize_title(&info.title);
+    async fn get_metadata_by_id(&self, source: &SourceMeta) -> Result<Metadata> {
+        let provider = self.sources.get(source.provider)?;
+        let info = provider.get_info_by_id(&source.id).await?;
+        let normalized_title = provider.normalize_title(&info.title);
         self.metadata_provider
             .fetch_metadata(&normalized_title)
             .await
@@ -195,6 +546,11 @@ impl AppService {
     pub async fn delete(&mut self, id: &str) -> Result<()> {
         log::debug!("Removing {} from torrent client", id);
         self.torrent_service.lock().await.remove_torrent(id).await?;
+        let had_limits = self.settings.bandwidth.torrents.remove(id).is_some();
+        let had_policy = self.settings.seeding.torrents.remove(id).is_some();
+        if had_limits || had_policy {
+            self.settings.write(&self.base_dir).await?;
+        }
         log::info!("Removing {} from library", id);
         self.library.delete(id).await
     }
@@ -314,4 +670,389 @@ impl AppService {
             .update_library_entry_title(id, title, metadata)
             .await
     }
+
+    /// Import a local folder or set of cbz files as a new library entry.
+    /// The entry is named after `title`, or the folder / first file when missing.
+    /// Titles that aren't a plain folder name, like `../Frieren`, are refused.
+    pub async fn import_local(
+        &mut self,
+        paths: Vec<PathBuf>,
+        title: Option<String>,
+        mode: ImportMode,
+    ) -> Result<LibraryEntry> {
+        let name = match title {
+            Some(title) => entry_dir_name(&title)?.to_owned(),
+            None => {
+                let path = paths.first().context("Nothing to import")?;
+                let name = if path.is_dir() {
+                    path.file_name()
+                } else {
+                    path.file_stem()
+                };
+                name.context(format!("Invalid path to import: {}", path.display()))?
+                    .to_string_lossy()
+                    .to_string()
+            }
+        };
+
+        let output_dir = self.base_dir.join("library").join(&name);
+        Library::import_files(&paths, &output_dir, mode).await?;
+
+        // imported folders usually keep their release names, so clean them up the way nyaa titles are
+        let normalized_title = self.sources.get(Sources::Nyaa)?.normalize_title(&name);
+        let metadata = self
+            .metadata_provider
+            .fetch_metadata(&normalized_title)
+            .await
+            .inspect_err(|err| log::warn!("No metdata found for \"{}\": {}", name, err))
+            .ok();
+
+        let metafile = Metafile::new(SourceMeta::local(), metadata);
+        let id = metafile.source.id.clone();
+
+        log::debug!("Writing metafile for {}", id);
+        metafile.write(&output_dir).await?;
+
+        self.library.add_entry(metafile, output_dir).await?;
+        self.library
+            .get_entry(&id)
+            .await
+            .context(format!("Failed to find entry with id {} in library", id))
+    }
+
+    pub fn list_saved_searches(&self) -> Vec<SavedSearch> {
+        self.saved_searches.list()
+    }
+
+    /// Save `query` to be checked for new releases,
+    /// anything it currently finds is not considered new
+    pub async fn add_saved_search(
+        &mut self,
+        name: &str,
+        query: SearchQuery,
+    ) -> Result<SavedSearch> {
+        let query = SearchQuery {
+            page: None,
+            ..query
+        };
+        let (results, _) = self
+            .sources
+            .search(&SearchQuery {
+                refresh: true,
+                ..query.clone()
+            })
+            .await?;
+        let results: Vec<MediaInfo> = results.into_iter().map(|(_, info)| info).collect();
+
+        self.saved_searches.add(name, query, &results).await
+    }
+
+    pub async fn remove_saved_search(&mut self, id: &str) -> Result<()> {
+        self.saved_searches.remove(id).await
+    }
+
+    /// Re-run the saved search with `id`, returning any results that haven't been seen before
+    pub async fn check_saved_search(&mut self, id: &str) -> Result<NewReleases> {
+        let search = self
+            .saved_searches
+            .list()
+            .into_iter()
+            .find(|search| search.id == id)
+            .context(format!("Missing saved search for {}", id))?;
+
+        log::debug!("Checking saved search \"{}\" for new releases", search.name);
+        let (results, _) = self
+            .sources
+            .search(&SearchQuery {
+                refresh: true,
+                ..search.query
+            })
+            .await?;
+
+        self.saved_searches
+            .record_results(id, results.into_iter().map(|(_, info)| info).collect())
+            .await
+    }
+
+    pub fn saved_search_poll_interval(&self) -> Duration {
+        self.settings.saved_searches.poll_interval()
+    }
+
+    pub fn list_download_rules(&self) -> Vec<DownloadRule> {
+        self.download_rules.list()
+    }
+
+    /// Save `rule`, anything its query currently finds is left alone
+    /// and only releases that show up later are downloaded
+    pub async fn add_download_rule(&mut self, rule: DownloadRule) -> Result<DownloadRule> {
+        let (results, _) = self
+            .sources
+            .search(&SearchQuery {
+                page: None,
+                refresh: true,
+                ..rule.query.clone()
+            })
+            .await?;
+        let results: Vec<MediaInfo> = results.into_iter().map(|(_, info)| info).collect();
+
+        self.download_rules.add(rule, &results).await
+    }
+
+    pub async fn remove_download_rule(&mut self, id: &str) -> Result<()> {
+        self.download_rules.remove(id).await
+    }
+
+    /// Results `rule` would download right now, without downloading them.
+    /// The rule doesn't have to be saved, so it can be tried out before adding it,
+    /// though once added it skips these and waits for new releases.
+    pub async fn find_rule_matches(&self, rule: &DownloadRule) -> Result<Vec<RuleMatch>> {
+        let (results, _) = self
+            .sources
+            .search(&SearchQuery {
+                page: None,
+                refresh: true,
+                ..rule.query.clone()
+            })
+            .await?;
+
+        Ok(rule
+            .find_matches(results)?
+            .into_iter()
+            .map(|(provider, media_info)| RuleMatch {
+                provider,
+                media_info,
+            })
+            .collect())
+    }
+
+    /// Matches for the saved rule with `id`, nothing when the rule is disabled
+    pub async fn find_saved_rule_matches(&self, id: &str) -> Result<Vec<RuleMatch>> {
+        let rule = self.download_rules.get(id)?;
+        if !rule.enabled {
+            return Ok(vec![]);
+        }
+        self.find_rule_matches(&rule).await
+    }
+
+    pub async fn record_rule_download(
+        &mut self,
+        rule_id: &str,
+        rule_match: &RuleMatch,
+    ) -> Result<()> {
+        self.download_rules
+            .record_download(rule_id, rule_match.provider, &rule_match.media_info)
+            .await
+    }
+
+    pub fn list_favourite_uploaders(&self) -> Vec<FavouriteUploader> {
+        self.favourite_uploaders.list()
+    }
+
+    pub async fn add_favourite_uploader(
+        &mut self,
+        name: &str,
+        provider: Sources,
+    ) -> Result<FavouriteUploader> {
+        self.favourite_uploaders.add(name, provider).await
+    }
+
+    pub async fn remove_favourite_uploader(&mut self, name: &str, provider: Sources) -> Result<()> {
+        self.favourite_uploaders.remove(name, provider).await
+    }
+
+    pub fn get_bandwidth_limits(&self) -> BandwidthSettings {
+        self.settings.bandwidth.clone()
+    }
+
+    /// Limits below `MIN_LIMIT_BPS` are raised to it
+    pub async fn set_global_bandwidth_limits(&mut self, limits: BandwidthLimits) -> Result<()> {
+        let limits = limits.clamped();
+        self.torrent_service.lock().await.set_global_limits(limits);
+        self.settings.bandwidth.global = limits;
+        self.settings.write(&self.base_dir).await
+    }
+
+    /// Limits for the download `id` on top of the global ones, unlimited limits remove the override.
+    /// Limits below `MIN_LIMIT_BPS` are raised to it.
+    pub async fn set_torrent_bandwidth_limits(
+        &mut self,
+        id: &str,
+        limits: BandwidthLimits,
+    ) -> Result<()> {
+        let limits = limits.clamped();
+        self.torrent_service
+            .lock()
+            .await
+            .set_torrent_limits(id, limits)
+            .await?;
+
+        if limits.is_unlimited() {
+            self.settings.bandwidth.torrents.remove(id);
+        } else {
+            self.settings
+                .bandwidth
+                .torrents
+                .insert(id.to_owned(), limits);
+        }
+        self.settings.write(&self.base_dir).await
+    }
+
+    /// Start queued downloads if any active ones have finished
+    pub async fn promote_queued_downloads(&self) {
+        self.torrent_service.lock().await.promote().await
+    }
+
+    pub async fn set_max_active_downloads(&mut self, max_active: usize) -> Result<()> {
+        self.torrent_service
+            .lock()
+            .await
+            .set_max_active(max_active)
+            .await;
+        self.settings.queue.max_active_downloads = max_active;
+        self.settings.write(&self.base_dir).await
+    }
+
+    /// Reorder the download queue, `position` 0 is the next download to start
+    pub async fn move_queued_download(&self, id: &str, position: usize) -> Result<()> {
+        self.torrent_service
+            .lock()
+            .await
+            .move_queued(id, position)
+            .await
+    }
+
+    pub fn get_seeding_policies(&self) -> SeedingSettings {
+        self.settings.seeding.clone()
+    }
+
+    pub async fn set_global_seeding_policy(&mut self, policy: SeedingPolicy) -> Result<()> {
+        self.torrent_service
+            .lock()
+            .await
+            .set_global_seeding_policy(policy);
+        self.settings.seeding.global = policy;
+        self.settings.write(&self.base_dir).await
+    }
+
+    /// Policy for the download `id`, `None` falls back to the global policy
+    pub async fn set_torrent_seeding_policy(
+        &mut self,
+        id: &str,
+        policy: Option<SeedingPolicy>,
+    ) -> Result<()> {
+        self.torrent_service
+            .lock()
+            .await
+            .set_torrent_seeding_policy(id, policy);
+        match policy {
+            Some(policy) => self.settings.seeding.torrents.insert(id.to_owned(), policy),
+            None => self.settings.seeding.torrents.remove(id),
+        };
+        self.settings.write(&self.base_dir).await
+    }
+
+    /// Stop seeding any torrents that met their seeding policy, returning their ids
+    pub async fn enforce_seeding_policies(&self) -> Vec<String> {
+        self.torrent_service
+            .lock()
+            .await
+            .enforce_seeding_policies()
+            .await
+    }
+
+    pub async fn list_broken_torrents(&self) -> Vec<BrokenTorrent> {
+        self.torrent_service.lock().await.broken_torrents()
+    }
+
+    /// Repair the broken torrent `torrent_id`, returning the id of the download it became
+    pub async fn repair_torrent(
+        &mut self,
+        torrent_id: usize,
+        action: RepairAction,
+    ) -> Result<Option<String>> {
+        log::info!("Repairing torrent {} with {:?}", torrent_id, action);
+        let (source_id, output_dir, new_metafile) = match action {
+            RepairAction::Forget => {
+                self.torrent_service
+                    .lock()
+                    .await
+                    .forget_torrent(torrent_id)
+                    .await?;
+                return Ok(None);
+            }
+            RepairAction::Relink { source_id } => {
+                let entry = self
+                    .library
+                    .get_entry(&source_id)
+                    .await
+                    .context(format!("Missing library entry for {}", source_id))?;
+                (source_id, entry.output_dir, None)
+            }
+            RepairAction::Recreate => self.recreate_library_entry(torrent_id).await?,
+        };
+
+        self.torrent_service
+            .lock()
+            .await
+            .repair_torrent(torrent_id, &source_id, &output_dir)
+            .await?;
+
+        // a new entry is only written once its torrent is back, so a failed repair leaves nothing
+        if let Some(metafile) = new_metafile {
+            metafile.write(&output_dir).await?;
+            self.library.add_entry(metafile, output_dir).await?;
+        } else {
+            self.library.refresh_files(&source_id).await?;
+        }
+        Ok(Some(source_id))
+    }
+
+    /// The library entry for the broken torrent `torrent_id`, reusing the one in its folder if any.
+    /// A new entry comes with the metafile to write for it.
+    async fn recreate_library_entry(
+        &self,
+        torrent_id: usize,
+    ) -> Result<(String, PathBuf, Option<Metafile>)> {
+        let broken = self
+            .list_broken_torrents()
+            .await
+            .into_iter()
+            .find(|torrent| torrent.torrent_id == torrent_id)
+            .context(format!("Torrent {} doesn't need repairing", torrent_id))?;
+
+        // the name comes from the torrent, so only a plain folder name is used
+        let output_dir = match broken.output_dir {
+            Some(output_dir) => output_dir,
+            None => self.base_dir.join("library").join(
+                entry_dir_name(&broken.name)
+                    .map(str::to_owned)
+                    .unwrap_or_else(|_| format!("torrent-{}", torrent_id)),
+            ),
+        };
+
+        let entries = self.library.get_entries();
+        if let Some(entry) = entries.iter().find(|entry| entry.output_dir == output_dir) {
+            return Ok((entry.metafile.source.id.clone(), output_dir, None));
+        }
+
+        // torrent files tend to link back to their upload, which keeps the entry's lookups working.
+        // Without one it becomes local content like an import. An upload that already has an entry
+        // elsewhere is left to relinking rather than given a second one.
+        let source = broken
+            .comment
+            .as_deref()
+            .and_then(|comment| self.sources.source_from_url(comment))
+            .filter(|source| {
+                !entries
+                    .iter()
+                    .any(|entry| entry.metafile.source.id == source.id)
+            })
+            .unwrap_or_else(SourceMeta::local);
+        let metadata = match source.provider {
+            Sources::Local => None,
+            _ => self.get_metadata_by_id(&source).await.ok(),
+        };
+        let metafile = Metafile::new(source, metadata);
+        Ok((metafile.source.id.clone(), output_dir, Some(metafile)))
+    }
 }
@@ -1,20 +1,47 @@
-use std::time::{Duration, Instant};
+use std::{
+    path::PathBuf,
+    time::{Duration, Instant},
+};
 
-use tauri::{Emitter, State};
-use tokio::sync::Mutex;
+use anyhow::Context;
+use tauri::{ipc::Channel, Emitter, Manager, State};
+use tokio::sync::{watch::Receiver, Mutex};
 
 use crate::{
-    app_service::{AppService, SearchResponse},
-    library::{LibraryEntry, LibraryEntrySettings},
-    torrent::TorrentStats,
+    app_service::{AppService, RepairAction, SearchResponse, SearchResult, MAX_SEARCH_PAGES},
+    download_rule::{DownloadRule, RuleMatch},
+    favourite_uploader::FavouriteUploader,
+    library::{ImportMode, Library, LibraryEntry, LibraryEntrySettings},
+    saved_search::SavedSearch,
+    search_group::GroupedSearchResponse,
+    settings::{BandwidthSettings, SeedingSettings},
+    source::{SearchQuery, SourceMeta, Sources, TorrentDetails},
+    torrent::{BandwidthLimits, BrokenTorrent, SeedingPolicy, TorrentFile, TorrentStats},
 };
 
+const QUEUE_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+const SEEDING_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
 #[tauri::command]
 pub async fn download(
     app_handle: tauri::AppHandle,
     state: State<'_, Mutex<AppService>>,
     id: String,
+    provider: Sources,
+    only_files: Option<Vec<usize>>,
+) -> Result<(), String> {
+    start_download(&app_handle, &state, SourceMeta { id, provider }, only_files).await
+}
+
+/// Download `source` into the library, streaming its progress as `download-progress` events
+async fn start_download(
+    app_handle: &tauri::AppHandle,
+    state: &Mutex<AppService>,
+    source: SourceMeta,
+    only_files: Option<Vec<usize>>,
 ) -> Result<(), String> {
+    let id = source.id.clone();
+
     app_handle
         .emit(
             "download-started",
@@ -23,27 +50,41 @@ pub async fn download(
                 state
                     .lock()
                     .await
-                    .get_title_by_id(&id)
+                    .get_title_by_id(&source)
                     .await
                     .unwrap_or("".to_owned()),
             ),
         )
         .map_err(|e| e.to_string())?;
 
+    // only hold the lock to grab the downloader and record the download,
+    // resolving the torrent can take up to the magnet timeout
+    let downloader = state.lock().await.downloader();
+    let output_dir = downloader
+        .download(&source, only_files)
+        .await
+        .map_err(|e| e.to_string())?;
     state
         .lock()
         .await
-        .download(&id)
+        .add_download(source, output_dir)
         .await
         .map_err(|e| e.to_string())?;
 
-    let mut rx = state
+    let rx = state
         .lock()
         .await
         .get_torrent_stats_receiver(&id)
         .await
         .map_err(|e| e.to_string())?;
+    emit_progress(app_handle, rx);
+    watch_completion(app_handle, id);
+
+    Ok(())
+}
 
+/// Forward stats from `rx` as `download-progress` events until the download stops
+fn emit_progress(app_handle: &tauri::AppHandle, mut rx: Receiver<TorrentStats>) {
     tokio::spawn({
         let app_handle = app_handle.clone();
         async move {
@@ -61,16 +102,67 @@ pub async fn download(
             Ok::<(), String>(())
         }
     });
+}
 
-    Ok(())
+/// Once the download `id` finishes, update its library entry and emit `download-finished`
+fn watch_completion(app_handle: &tauri::AppHandle, id: String) {
+    tauri::async_runtime::spawn({
+        let app_handle = app_handle.clone();
+        async move {
+            let state = app_handle.state::<Mutex<AppService>>();
+            let finished = state.lock().await.wait_until_finished(&id).await;
+            if let Err(err) = async { finished?.await }.await {
+                log::warn!("Stopped waiting for {} to finish: {}", id, err);
+                return;
+            }
+
+            // the lock is only taken again once the download is done,
+            // and not while the archives are read through
+            let result = async {
+                let (output_dir, new_files) =
+                    state.lock().await.refresh_download_files(&id).await?;
+                let invalid_archives = Library::validate_archives(&output_dir, new_files).await?;
+                state
+                    .lock()
+                    .await
+                    .complete_download(&id, invalid_archives)
+                    .await
+            }
+            .await;
+            match result {
+                Ok(finished) => {
+                    if let Err(err) = app_handle.emit("download-finished", finished) {
+                        log::error!("Failed to emit finished download: {}", err);
+                    }
+                }
+                Err(err) => log::error!("Failed to complete download {}: {}", id, err),
+            }
+        }
+    });
+}
+
+/// Pick up downloads restored from the last session that haven't finished yet
+pub fn watch_unfinished_downloads(app_handle: tauri::AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        let state = app_handle.state::<Mutex<AppService>>();
+        let ids = state.lock().await.unfinished_downloads().await;
+        for id in ids {
+            watch_completion(&app_handle, id);
+        }
+    });
 }
 
 #[tauri::command]
 pub async fn search(
     state: State<'_, Mutex<AppService>>,
-    query: String,
+    query: SearchQuery,
+    refresh: Option<bool>,
 ) -> Result<SearchResponse, String> {
     let now = Instant::now();
+    let query = SearchQuery {
+        refresh: refresh.unwrap_or(false),
+        ..query
+    };
     let res = state
         .lock()
         .await
@@ -83,6 +175,115 @@ pub async fn search(
     res
 }
 
+#[tauri::command]
+pub async fn search_grouped(
+    state: State<'_, Mutex<AppService>>,
+    query: SearchQuery,
+    refresh: Option<bool>,
+) -> Result<GroupedSearchResponse, String> {
+    let query = SearchQuery {
+        refresh: refresh.unwrap_or(false),
+        ..query
+    };
+    state
+        .lock()
+        .await
+        .search_grouped(query)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Search every page of `query` up to `max_pages`, sending each page over `on_page` as it arrives
+#[tauri::command]
+pub async fn search_all(
+    state: State<'_, Mutex<AppService>>,
+    query: SearchQuery,
+    max_pages: Option<u32>,
+    refresh: Option<bool>,
+    on_page: Channel<SearchResponse>,
+) -> Result<(), String> {
+    let now = Instant::now();
+    let query = SearchQuery {
+        refresh: refresh.unwrap_or(false),
+        ..query
+    };
+    // only hold the lock long enough to grab the searcher, walking the pages can take a while
+    let searcher = state.lock().await.searcher();
+    let res = searcher
+        .search_all(query, max_pages.unwrap_or(MAX_SEARCH_PAGES), |page| {
+            on_page.send(page).context("Failed to send search page")
+        })
+        .await
+        .map_err(|e| e.to_string());
+    log::info!("Searching all pages took {}ms", now.elapsed().as_millis());
+
+    res
+}
+
+#[tauri::command]
+pub async fn get_torrent_details(
+    state: State<'_, Mutex<AppService>>,
+    id: String,
+    provider: Sources,
+) -> Result<TorrentDetails, String> {
+    state
+        .lock()
+        .await
+        .get_torrent_details(&SourceMeta { id, provider })
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Files in a release, so a subset can be picked before downloading it
+#[tauri::command]
+pub async fn list_source_files(
+    state: State<'_, Mutex<AppService>>,
+    id: String,
+    provider: Sources,
+) -> Result<Vec<TorrentFile>, String> {
+    state
+        .lock()
+        .await
+        .list_source_files(&SourceMeta { id, provider })
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn get_download_files(
+    state: State<'_, Mutex<AppService>>,
+    id: String,
+) -> Result<Vec<TorrentFile>, String> {
+    state
+        .lock()
+        .await
+        .get_download_files(&id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn update_download_files(
+    app_handle: tauri::AppHandle,
+    state: State<'_, Mutex<AppService>>,
+    id: String,
+    only_files: Vec<usize>,
+) -> Result<(), String> {
+    state
+        .lock()
+        .await
+        .update_download_files(&id, only_files)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    // adding files to a finished download starts it again
+    if let Ok(rx) = state.lock().await.get_torrent_stats_receiver(&id).await {
+        emit_progress(&app_handle, rx);
+    }
+
+    Ok(())
+}
+
 #[tauri::command]
 pub async fn list_torrents(
     state: State<'_, Mutex<AppService>>,
@@ -103,24 +304,144 @@ pub async fn toggle_pause(
         .await
         .map_err(|e| e.to_string())?;
 
-    if let Ok(mut rx) = state.lock().await.get_torrent_stats_receiver(&id).await {
-        tokio::spawn({
-            let app_handle = app_handle.clone();
-            async move {
-                loop {
-                    tokio::time::sleep(Duration::from_secs(1)).await;
-                    if rx.changed().await.is_err() {
-                        break;
-                    }
-                    let stats = rx.borrow();
-                    app_handle
-                        .emit("download-progress", stats.clone())
-                        .map_err(|e| e.to_string())?;
-                }
+    if let Ok(rx) = state.lock().await.get_torrent_stats_receiver(&id).await {
+        emit_progress(&app_handle, rx);
+    }
 
-                Ok::<(), String>(())
-            }
-        });
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn get_bandwidth_limits(
+    state: State<'_, Mutex<AppService>>,
+) -> Result<BandwidthSettings, String> {
+    Ok(state.lock().await.get_bandwidth_limits())
+}
+
+#[tauri::command]
+pub async fn set_global_bandwidth_limits(
+    state: State<'_, Mutex<AppService>>,
+    limits: BandwidthLimits,
+) -> Result<(), String> {
+    state
+        .lock()
+        .await
+        .set_global_bandwidth_limits(limits)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Changing the limits of an active download adds it to the session again,
+/// which rechecks the files already on disk before it carries on
+#[tauri::command]
+pub async fn set_torrent_bandwidth_limits(
+    app_handle: tauri::AppHandle,
+    state: State<'_, Mutex<AppService>>,
+    id: String,
+    limits: BandwidthLimits,
+) -> Result<(), String> {
+    state
+        .lock()
+        .await
+        .set_torrent_bandwidth_limits(&id, limits)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    // the torrent is added again to apply the limits, which replaces its stats receiver
+    if let Ok(rx) = state.lock().await.get_torrent_stats_receiver(&id).await {
+        emit_progress(&app_handle, rx);
+    }
+
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn get_seeding_policies(
+    state: State<'_, Mutex<AppService>>,
+) -> Result<SeedingSettings, String> {
+    Ok(state.lock().await.get_seeding_policies())
+}
+
+#[tauri::command]
+pub async fn set_global_seeding_policy(
+    state: State<'_, Mutex<AppService>>,
+    policy: SeedingPolicy,
+) -> Result<(), String> {
+    state
+        .lock()
+        .await
+        .set_global_seeding_policy(policy)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn set_torrent_seeding_policy(
+    state: State<'_, Mutex<AppService>>,
+    id: String,
+    policy: Option<SeedingPolicy>,
+) -> Result<(), String> {
+    state
+        .lock()
+        .await
+        .set_torrent_seeding_policy(&id, policy)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn set_max_active_downloads(
+    state: State<'_, Mutex<AppService>>,
+    max_active: usize,
+) -> Result<(), String> {
+    state
+        .lock()
+        .await
+        .set_max_active_downloads(max_active)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn move_queued_download(
+    state: State<'_, Mutex<AppService>>,
+    id: String,
+    position: usize,
+) -> Result<(), String> {
+    state
+        .lock()
+        .await
+        .move_queued_download(&id, position)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn list_broken_torrents(
+    state: State<'_, Mutex<AppService>>,
+) -> Result<Vec<BrokenTorrent>, String> {
+    Ok(state.lock().await.list_broken_torrents().await)
+}
+
+#[tauri::command]
+pub async fn repair_torrent(
+    app_handle: tauri::AppHandle,
+    state: State<'_, Mutex<AppService>>,
+    torrent_id: usize,
+    action: RepairAction,
+) -> Result<(), String> {
+    let repaired = state
+        .lock()
+        .await
+        .repair_torrent(torrent_id, action)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    if let Some(id) = repaired {
+        if let Ok(rx) = state.lock().await.get_torrent_stats_receiver(&id).await {
+            emit_progress(&app_handle, rx);
+        }
+        watch_completion(&app_handle, id);
     }
 
     Ok(())
@@ -269,3 +590,266 @@ pub async fn update_library_entry_title(
         .await
         .map_err(|e| e.to_string())
 }
+
+#[tauri::command]
+pub async fn import_local(
+    state: State<'_, Mutex<AppService>>,
+    paths: Vec<PathBuf>,
+    title: Option<String>,
+    mode: ImportMode,
+) -> Result<LibraryEntry, String> {
+    state
+        .lock()
+        .await
+        .import_local(paths, title, mode)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn list_saved_searches(
+    state: State<'_, Mutex<AppService>>,
+) -> Result<Vec<SavedSearch>, String> {
+    Ok(state.lock().await.list_saved_searches())
+}
+
+#[tauri::command]
+pub async fn add_saved_search(
+    state: State<'_, Mutex<AppService>>,
+    name: String,
+    query: SearchQuery,
+) -> Result<SavedSearch, String> {
+    state
+        .lock()
+        .await
+        .add_saved_search(&name, query)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn remove_saved_search(
+    state: State<'_, Mutex<AppService>>,
+    id: String,
+) -> Result<(), String> {
+    state
+        .lock()
+        .await
+        .remove_saved_search(&id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Periodically re-run every saved search, emitting `new-releases` for any that found something new,
+/// then download anything matched by the download rules
+pub fn spawn_background_poller(app_handle: tauri::AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        loop {
+            let state = app_handle.state::<Mutex<AppService>>();
+            let interval = state.lock().await.saved_search_poll_interval();
+            tokio::time::sleep(interval).await;
+
+            check_saved_searches(&app_handle, &state).await;
+            run_download_rules(&app_handle, &state).await;
+        }
+    });
+}
+
+/// Start the next queued downloads whenever active ones finish
+pub fn spawn_queue_promoter(app_handle: tauri::AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        loop {
+            tokio::time::sleep(QUEUE_CHECK_INTERVAL).await;
+            let state = app_handle.state::<Mutex<AppService>>();
+            state.lock().await.promote_queued_downloads().await;
+        }
+    });
+}
+
+/// Stop seeding torrents that met their seeding policy, emitting `seeding-stopped` with their ids
+pub fn spawn_seeding_enforcer(app_handle: tauri::AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        loop {
+            tokio::time::sleep(SEEDING_CHECK_INTERVAL).await;
+            let state = app_handle.state::<Mutex<AppService>>();
+            let stopped = state.lock().await.enforce_seeding_policies().await;
+            if !stopped.is_empty() {
+                if let Err(err) = app_handle.emit("seeding-stopped", stopped) {
+                    log::error!("Failed to emit stopped seeds: {}", err);
+                }
+            }
+        }
+    });
+}
+
+async fn check_saved_searches(app_handle: &tauri::AppHandle, state: &Mutex<AppService>) {
+    let searches = state.lock().await.list_saved_searches();
+    for search in searches {
+        // lock per search so other commands aren't held up for the whole run
+        let result = state.lock().await.check_saved_search(&search.id).await;
+        match result {
+            Ok(new_releases) if !new_releases.releases.is_empty() => {
+                log::info!(
+                    "Found {} new releases for \"{}\"",
+                    new_releases.releases.len(),
+                    search.name
+                );
+                if let Err(err) = app_handle.emit("new-releases", new_releases) {
+                    log::error!("Failed to emit new releases: {}", err);
+                }
+            }
+            Ok(_) => {}
+            Err(err) => {
+                log::warn!("Failed to check saved search \"{}\": {}", search.name, err)
+            }
+        }
+    }
+}
+
+async fn run_download_rules(app_handle: &tauri::AppHandle, state: &Mutex<AppService>) {
+    let rules = state.lock().await.list_download_rules();
+    for rule in rules {
+        let matches = match state.lock().await.find_saved_rule_matches(&rule.id).await {
+            Ok(matches) => matches,
+            Err(err) => {
+                log::warn!("Failed to run download rule \"{}\": {}", rule.name, err);
+                continue;
+            }
+        };
+
+        for rule_match in matches {
+            log::info!(
+                "Download rule \"{}\" matched {}",
+                rule.name,
+                rule_match.media_info.title
+            );
+            let source = SourceMeta {
+                id: rule_match.media_info.id.clone(),
+                provider: rule_match.provider,
+            };
+            if let Err(err) = start_download(app_handle, state, source, None).await {
+                log::warn!(
+                    "Failed to download {}: {}",
+                    rule_match.media_info.title,
+                    err
+                );
+                continue;
+            }
+
+            if let Err(err) = state
+                .lock()
+                .await
+                .record_rule_download(&rule.id, &rule_match)
+                .await
+            {
+                log::error!(
+                    "Failed to record download for rule \"{}\": {}",
+                    rule.name,
+                    err
+                );
+            }
+        }
+    }
+}
+
+#[tauri::command]
+pub async fn list_download_rules(
+    state: State<'_, Mutex<AppService>>,
+) -> Result<Vec<DownloadRule>, String> {
+    Ok(state.lock().await.list_download_rules())
+}
+
+#[tauri::command]
+pub async fn add_download_rule(
+    state: State<'_, Mutex<AppService>>,
+    rule: DownloadRule,
+) -> Result<DownloadRule, String> {
+    state
+        .lock()
+        .await
+        .add_download_rule(rule)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn remove_download_rule(
+    state: State<'_, Mutex<AppService>>,
+    id: String,
+) -> Result<(), String> {
+    state
+        .lock()
+        .await
+        .remove_download_rule(&id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// What `rule` would download right now, without downloading anything
+#[tauri::command]
+pub async fn dry_run_download_rule(
+    state: State<'_, Mutex<AppService>>,
+    rule: DownloadRule,
+) -> Result<Vec<RuleMatch>, String> {
+    state
+        .lock()
+        .await
+        .find_rule_matches(&rule)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn list_favourite_uploaders(
+    state: State<'_, Mutex<AppService>>,
+) -> Result<Vec<FavouriteUploader>, String> {
+    Ok(state.lock().await.list_favourite_uploaders())
+}
+
+#[tauri::command]
+pub async fn add_favourite_uploader(
+    state: State<'_, Mutex<AppService>>,
+    name: String,
+    provider: Sources,
+) -> Result<FavouriteUploader, String> {
+    state
+        .lock()
+        .await
+        .add_favourite_uploader(&name, provider)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn remove_favourite_uploader(
+    state: State<'_, Mutex<AppService>>,
+    name: String,
+    provider: Sources,
+) -> Result<(), String> {
+    state
+        .lock()
+        .await
+        .remove_favourite_uploader(&name, provider)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn uploader_feed(
+    state: State<'_, Mutex<AppService>>,
+    query: SearchQuery,
+    refresh: Option<bool>,
+) -> Result<Vec<SearchResult>, String> {
+    let query = SearchQuery {
+        refresh: refresh.unwrap_or(false),
+        ..query
+    };
+    let (searcher, uploaders) = {
+        let state = state.lock().await;
+        (state.searcher(), state.list_favourite_uploaders())
+    };
+    searcher
+        .uploader_feed(&uploaders, query)
+        .await
+        .map_err(|e| e.to_string())
+}
@@ -0,0 +1,344 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use regex::RegexBuilder;
+use serde::{Deserialize, Serialize};
+use serde_json::{from_str, to_vec};
+use tokio::{
+    fs::{read_to_string, File},
+    io::AsyncWriteExt,
+};
+use uuid::Uuid;
+
+use crate::source::{Category, MediaInfo, ReleaseStatus, SearchQuery, Sources};
+
+const DOWNLOAD_RULES_FILE: &str = "download_rules.json";
+
+/// A result that would be downloaded by a rule
+#[derive(Serialize, Clone, Debug)]
+pub struct RuleMatch {
+    pub provider: Sources,
+    pub media_info: MediaInfo,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct RuleDownload {
+    pub id: String,
+    pub provider: Sources,
+    pub title: String,
+    pub downloaded_at: DateTime<Utc>,
+}
+
+/// Downloads results of `query` automatically when they pass every filter
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct DownloadRule {
+    pub id: String,
+    pub name: String,
+    pub enabled: bool,
+    pub query: SearchQuery,
+    /// Case insensitive regex the release title has to match
+    pub title_pattern: Option<String>,
+    pub min_seeders: Option<u32>,
+    pub category: Option<Category>,
+    pub trusted_only: bool,
+    /// Size bounds in bytes, inclusive
+    pub min_size: Option<u64>,
+    pub max_size: Option<u64>,
+    /// Everything downloaded by this rule, so nothing is downloaded twice
+    pub history: Vec<RuleDownload>,
+    /// Ids of results that were already there when the rule was added, which it never downloads
+    pub seen_ids: Vec<String>,
+}
+
+impl Default for DownloadRule {
+    fn default() -> Self {
+        DownloadRule {
+            id: "".to_owned(),
+            name: "".to_owned(),
+            enabled: true,
+            query: SearchQuery::default(),
+            title_pattern: None,
+            min_seeders: None,
+            category: None,
+            trusted_only: false,
+            min_size: None,
+            max_size: None,
+            history: vec![],
+            seen_ids: vec![],
+        }
+    }
+}
+
+impl DownloadRule {
+    /// Results that pass the filters and that this rule hasn't seen or downloaded before
+    pub fn find_matches(
+        &self,
+        results: Vec<(Sources, MediaInfo)>,
+    ) -> Result<Vec<(Sources, MediaInfo)>> {
+        let title_pattern = self
+            .title_pattern
+            .as_ref()
+            .map(|pattern| {
+                RegexBuilder::new(pattern)
+                    .case_insensitive(true)
+                    .build()
+                    .context(format!("Invalid title pattern: {}", pattern))
+            })
+            .transpose()?;
+
+        Ok(results
+            .into_iter()
+            .filter(|(provider, info)| {
+                !self.seen_ids.contains(&info.id)
+                    && !self
+                        .history
+                        .iter()
+                        .any(|download| download.id == info.id && download.provider == *provider)
+            })
+            .filter(|(_, info)| {
+                title_pattern
+                    .as_ref()
+                    .map_or(true, |pattern| pattern.is_match(&info.title))
+                    && self.min_seeders.map_or(true, |min| info.seeders >= min)
+                    && self
+                        .category
+                        .as_ref()
+                        .map_or(true, |category| *category == info.category)
+                    && (!self.trusted_only || info.status == ReleaseStatus::Trusted)
+                    && self.min_size.map_or(true, |min| info.size.bytes >= min)
+                    && self.max_size.map_or(true, |max| info.size.bytes <= max)
+            })
+            .collect())
+    }
+}
+
+pub struct DownloadRules {
+    path: PathBuf,
+    rules: Vec<DownloadRule>,
+}
+
+impl DownloadRules {
+    /// Read the rules from `dir`, starting empty if none have been made yet
+    pub async fn read(dir: &Path) -> Result<Self> {
+        let path = dir.join(DOWNLOAD_RULES_FILE);
+        let rules = if path.exists() {
+            let content = read_to_string(&path).await?;
+            from_str(&content).context(format!("Invalid download rules: {}", path.display()))?
+        } else {
+            vec![]
+        };
+
+        log::info!("Found {} download rules", rules.len());
+        Ok(DownloadRules { path, rules })
+    }
+
+    async fn write(&self) -> Result<()> {
+        let mut file = File::create(&self.path).await?;
+        file.write_all(to_vec(&self.rules)?.as_slice()).await?;
+        file.flush().await?;
+        log::trace!(
+            "Successfully wrote download rules to {}",
+            self.path.display()
+        );
+        Ok(())
+    }
+
+    pub fn list(&self) -> Vec<DownloadRule> {
+        self.rules.clone()
+    }
+
+    pub fn get(&self, id: &str) -> Result<DownloadRule> {
+        self.rules
+            .iter()
+            .find(|rule| rule.id == id)
+            .cloned()
+            .context(format!("Missing download rule for {}", id))
+    }
+
+    /// Add a rule with a fresh id and no history.
+    /// `current_results` are marked as seen so the first run doesn't download the whole backlog.
+    pub async fn add(
+        &mut self,
+        rule: DownloadRule,
+        current_results: &[MediaInfo],
+    ) -> Result<DownloadRule> {
+        let rule = DownloadRule {
+            id: Uuid::new_v4().to_string(),
+            history: vec![],
+            seen_ids: current_results.iter().map(|info| info.id.clone()).collect(),
+            ..rule
+        };
+        // catch invalid patterns now rather than on every run
+        rule.find_matches(vec![])?;
+
+        log::info!("Adding download rule \"{}\"", rule.name);
+        self.rules.push(rule.clone());
+        self.write().await?;
+        Ok(rule)
+    }
+
+    pub async fn remove(&mut self, id: &str) -> Result<()> {
+        let index = self
+            .rules
+            .iter()
+            .position(|rule| rule.id == id)
+            .context(format!("Missing download rule for {}", id))?;
+
+        let rule = self.rules.remove(index);
+        log::info!("Removed download rule \"{}\"", rule.name);
+        self.write().await
+    }
+
+    pub async fn record_download(
+        &mut self,
+        rule_id: &str,
+        provider: Sources,
+        info: &MediaInfo,
+    ) -> Result<()> {
+        let rule = self
+            .rules
+            .iter_mut()
+            .find(|rule| rule.id == rule_id)
+            .context(format!("Missing download rule for {}", rule_id))?;
+
+        rule.history.push(RuleDownload {
+            id: info.id.clone(),
+            provider,
+            title: info.title.clone(),
+            downloaded_at: Utc::now(),
+        });
+        self.write().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+    use tempdir::TempDir;
+
+    use crate::source::FileSize;
+
+    use super::*;
+
+    fn media_info(id: &str, title: &str) -> MediaInfo {
+        MediaInfo {
+            id: id.to_owned(),
+            category: Category::Manga,
+            title: title.to_owned(),
+            size: FileSize::from_bytes(200 * 1024 * 1024),
+            timestamp: DateTime::default(),
+            seeders: 10,
+            leechers: 0,
+            completed: 0,
+            info_hash: "".to_owned(),
+            status: ReleaseStatus::Trusted,
+        }
+    }
+
+    fn matches(rule: &DownloadRule, info: MediaInfo) -> bool {
+        !rule
+            .find_matches(vec![(Sources::Nyaa, info)])
+            .unwrap()
+            .is_empty()
+    }
+
+    #[rstest]
+    #[case(DownloadRule::default(), true)]
+    #[case(DownloadRule { title_pattern: Some(r"oshi no ko v\d+".to_owned()), ..Default::default() }, true)]
+    #[case(DownloadRule { title_pattern: Some("frieren".to_owned()), ..Default::default() }, false)]
+    #[case(DownloadRule { min_seeders: Some(10), ..Default::default() }, true)]
+    #[case(DownloadRule { min_seeders: Some(11), ..Default::default() }, false)]
+    #[case(DownloadRule { category: Some(Category::Manga), ..Default::default() }, true)]
+    #[case(DownloadRule { category: Some(Category::Anime), ..Default::default() }, false)]
+    #[case(DownloadRule { trusted_only: true, ..Default::default() }, true)]
+    #[case(DownloadRule { min_size: Some(100 * 1024 * 1024), max_size: Some(300 * 1024 * 1024), ..Default::default() }, true)]
+    #[case(DownloadRule { max_size: Some(100 * 1024 * 1024), ..Default::default() }, false)]
+    fn test_filters(#[case] rule: DownloadRule, #[case] expected: bool) {
+        assert_eq!(
+            matches(
+                &rule,
+                media_info("1", "Oshi no Ko v16 (2025) (Digital) (Oak)")
+            ),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_trusted_only() {
+        let rule = DownloadRule {
+            trusted_only: true,
+            ..Default::default()
+        };
+        let mut info = media_info("1", "Oshi no Ko v16");
+        info.status = ReleaseStatus::Remake;
+        assert!(!matches(&rule, info));
+    }
+
+    #[tokio::test]
+    async fn test_history() {
+        let dir = TempDir::new("download_rules").unwrap();
+        let mut rules = DownloadRules::read(dir.path()).await.unwrap();
+
+        let rule = rules
+            .add(
+                DownloadRule {
+                    name: "Oshi no Ko".to_owned(),
+                    ..Default::default()
+                },
+                &[],
+            )
+            .await
+            .unwrap();
+
+        let info = media_info("1", "Oshi no Ko v16");
+        rules
+            .record_download(&rule.id, Sources::Nyaa, &info)
+            .await
+            .unwrap();
+
+        // already downloaded by this rule
+        let rule = DownloadRules::read(dir.path())
+            .await
+            .unwrap()
+            .get(&rule.id)
+            .unwrap();
+        assert_eq!(rule.history.len(), 1);
+        assert!(!matches(&rule, info));
+        assert!(matches(&rule, media_info("2", "Oshi no Ko v17")));
+    }
+
+    #[tokio::test]
+    async fn test_invalid_pattern() {
+        let dir = TempDir::new("download_rules").unwrap();
+        let mut rules = DownloadRules::read(dir.path()).await.unwrap();
+
+        assert!(rules
+            .add(
+                DownloadRule {
+                    title_pattern: Some("v(".to_owned()),
+                    ..Default::default()
+                },
+                &[]
+            )
+            .await
+            .is_err());
+        assert!(rules.list().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_existing_results_are_skipped() {
+        let dir = TempDir::new("download_rules").unwrap();
+        let mut rules = DownloadRules::read(dir.path()).await.unwrap();
+
+        let existing = media_info("1", "Oshi no Ko v15");
+        let rule = rules
+            .add(DownloadRule::default(), std::slice::from_ref(&existing))
+            .await
+            .unwrap();
+
+        assert!(!matches(&rule, existing));
+        assert!(matches(&rule, media_info("2", "Oshi no Ko v16")));
+    }
+}
@@ -0,0 +1,130 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{from_str, to_vec};
+use tokio::{
+    fs::{read_to_string, File},
+    io::AsyncWriteExt,
+};
+
+use crate::source::Sources;
+
+const FAVOURITE_UPLOADERS_FILE: &str = "favourite_uploaders.json";
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct FavouriteUploader {
+    pub name: String,
+    pub provider: Sources,
+    pub added_at: DateTime<Utc>,
+}
+
+pub struct FavouriteUploaders {
+    path: PathBuf,
+    uploaders: Vec<FavouriteUploader>,
+}
+
+impl FavouriteUploaders {
+    /// Read the favourite uploaders from `dir`, starting empty if none have been saved yet
+    pub async fn read(dir: &Path) -> Result<Self> {
+        let path = dir.join(FAVOURITE_UPLOADERS_FILE);
+        let uploaders = if path.exists() {
+            let content = read_to_string(&path).await?;
+            from_str(&content)
+                .context(format!("Invalid favourite uploaders: {}", path.display()))?
+        } else {
+            vec![]
+        };
+
+        log::info!("Found {} favourite uploaders", uploaders.len());
+        Ok(FavouriteUploaders { path, uploaders })
+    }
+
+    async fn write(&self) -> Result<()> {
+        let mut file = File::create(&self.path).await?;
+        file.write_all(to_vec(&self.uploaders)?.as_slice()).await?;
+        file.flush().await?;
+        log::trace!(
+            "Successfully wrote favourite uploaders to {}",
+            self.path.display()
+        );
+        Ok(())
+    }
+
+    pub fn list(&self) -> Vec<FavouriteUploader> {
+        self.uploaders.clone()
+    }
+
+    pub async fn add(&mut self, name: &str, provider: Sources) -> Result<FavouriteUploader> {
+        let name = name.trim();
+        if name.is_empty() {
+            bail!("Uploader name can't be empty");
+        }
+        if self.position(name, provider).is_some() {
+            bail!("{} is already a favourite uploader", name);
+        }
+
+        let uploader = FavouriteUploader {
+            name: name.to_owned(),
+            provider,
+            added_at: Utc::now(),
+        };
+
+        log::info!("Adding favourite uploader {} on {:?}", name, provider);
+        self.uploaders.push(uploader.clone());
+        self.write().await?;
+        Ok(uploader)
+    }
+
+    pub async fn remove(&mut self, name: &str, provider: Sources) -> Result<()> {
+        let index = self
+            .position(name, provider)
+            .context(format!("{} is not a favourite uploader", name))?;
+
+        self.uploaders.remove(index);
+        log::info!("Removed favourite uploader {} on {:?}", name, provider);
+        self.write().await
+    }
+
+    /// Usernames on nyaa are case insensitive
+    fn position(&self, name: &str, provider: Sources) -> Option<usize> {
+        self.uploaders.iter().position(|uploader| {
+            uploader.provider == provider && uploader.name.eq_ignore_ascii_case(name)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempdir::TempDir;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_favourite_uploaders() {
+        let dir = TempDir::new("favourite_uploaders").unwrap();
+        let mut favourites = FavouriteUploaders::read(dir.path()).await.unwrap();
+
+        favourites.add("Ushi", Sources::Nyaa).await.unwrap();
+        favourites.add(" Oak ", Sources::Nyaa).await.unwrap();
+        assert!(favourites.add("ushi", Sources::Nyaa).await.is_err());
+        assert!(favourites.add("  ", Sources::Nyaa).await.is_err());
+
+        favourites.remove("USHI", Sources::Nyaa).await.unwrap();
+        assert!(favourites.remove("Ushi", Sources::Nyaa).await.is_err());
+
+        let names = |favourites: &FavouriteUploaders| -> Vec<String> {
+            favourites
+                .list()
+                .into_iter()
+                .map(|uploader| uploader.name)
+                .collect()
+        };
+        assert_eq!(names(&favourites), vec!["Oak"]);
+
+        // persisted across reads
+        let favourites = FavouriteUploaders::read(dir.path()).await.unwrap();
+        assert_eq!(names(&favourites), vec!["Oak"]);
+    }
+}
@@ -0,0 +1,403 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use rand::Rng;
+use reqwest::{header::RETRY_AFTER, Response, StatusCode};
+use serde::{Deserialize, Serialize};
+
+/// Errors for responses that a retry might fix, so callers can tell them apart
+/// from the rest by downcasting
+#[derive(Debug)]
+pub enum HttpError {
+    /// 429, with how long the server asked us to wait if it said
+    RateLimited {
+        url: String,
+        retry_after: Option<Duration>,
+    },
+    /// Any 5xx
+    Server { url: String, status: StatusCode },
+}
+
+impl fmt::Display for HttpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HttpError::RateLimited { url, retry_after } => match retry_after {
+                Some(retry_after) => write!(
+                    f,
+                    "Rate limited by {}, retry after {}s",
+                    url,
+                    retry_after.as_secs()
+                ),
+                None => write!(f, "Rate limited by {}", url),
+            },
+            HttpError::Server { url, status } => write!(f, "Server error from {}: {}", url, status),
+        }
+    }
+}
+
+impl std::error::Error for HttpError {}
+
+/// Lowest rate requests can be limited to, one every 100 seconds
+pub const MIN_REQUESTS_PER_SECOND: f64 = 0.01;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct HttpSettings {
+    /// Requests per second allowed to each host, unlimited when 0.
+    /// Lower rates are raised to `MIN_REQUESTS_PER_SECOND`.
+    pub requests_per_second: f64,
+    /// Retries for transient failures before giving up
+    pub max_retries: u32,
+    /// Delay before the first retry in milliseconds, doubled for every retry after
+    pub base_delay_ms: u64,
+    /// Upper bound for the delay between retries in milliseconds,
+    /// rate limits asking to wait longer fail right away
+    pub max_delay_ms: u64,
+}
+
+impl Default for HttpSettings {
+    fn default() -> Self {
+        HttpSettings {
+            requests_per_second: 2.0,
+            max_retries: 3,
+            base_delay_ms: 500,
+            max_delay_ms: 30_000,
+        }
+    }
+}
+
+impl HttpSettings {
+    /// Raise rates below `MIN_REQUESTS_PER_SECOND` to it, leaving unlimited ones alone
+    pub fn clamped(&self) -> HttpSettings {
+        let requests_per_second = if self.requests_per_second > 0.0 {
+            self.requests_per_second.max(MIN_REQUESTS_PER_SECOND)
+        } else {
+            self.requests_per_second
+        };
+        HttpSettings {
+            requests_per_second,
+            ..self.clone()
+        }
+    }
+}
+
+/// Shared wrapper around `reqwest::Client` that throttles requests per host
+/// and retries transient failures
+#[derive(Clone)]
+pub struct HttpClient {
+    client: reqwest::Client,
+    settings: HttpSettings,
+    /// Earliest time the next request to each host may be sent
+    next_request: Arc<Mutex<HashMap<String, Instant>>>,
+}
+
+impl HttpClient {
+    pub fn new(client: reqwest::Client) -> Self {
+        HttpClient {
+            client,
+            settings: HttpSettings::default(),
+            next_request: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    pub fn with_settings(mut self, settings: HttpSettings) -> Self {
+        self.settings = settings.clamped();
+        self
+    }
+
+    /// GET `url`, failing on any non 2xx status once retries are exhausted
+    pub async fn get(&self, url: &url::Url) -> Result<Response> {
+        let mut attempt = 0;
+        loop {
+            self.wait_for_turn(url).await;
+
+            let err = match self.client.get(url.as_str()).send().await {
+                Ok(response) => match HttpClient::check_status(url, response) {
+                    Ok(response) => return Ok(response),
+                    Err(err) => err,
+                },
+                Err(err) => err.into(),
+            };
+
+            let retryable = match err.downcast_ref::<HttpError>() {
+                Some(_) => true,
+                None => err
+                    .downcast_ref::<reqwest::Error>()
+                    .is_some_and(|err| err.is_connect() || err.is_timeout()),
+            };
+
+            if !retryable || attempt >= self.settings.max_retries {
+                return Err(err);
+            }
+
+            let delay = match err.downcast_ref::<HttpError>() {
+                Some(HttpError::RateLimited {
+                    retry_after: Some(retry_after),
+                    ..
+                }) => {
+                    // waiting longer than we would back off for holds up the caller too long,
+                    // leave it to them to try again later
+                    if *retry_after > Duration::from_millis(self.settings.max_delay_ms) {
+                        return Err(err);
+                    }
+                    *retry_after
+                }
+                _ => self.backoff(attempt),
+            };
+
+            attempt += 1;
+            log::warn!(
+                "{} (attempt {}/{}), retrying in {}ms",
+                err,
+                attempt,
+                self.settings.max_retries,
+                delay.as_millis()
+            );
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    fn check_status(url: &url::Url, response: Response) -> Result<Response> {
+        let status = response.status();
+        if status == StatusCode::TOO_MANY_REQUESTS {
+            let retry_after = response
+                .headers()
+                .get(RETRY_AFTER)
+                .and_then(|value| value.to_str().ok())
+                .and_then(parse_retry_after);
+            return Err(HttpError::RateLimited {
+                url: url.to_string(),
+                retry_after,
+            }
+            .into());
+        }
+
+        if status.is_server_error() {
+            return Err(HttpError::Server {
+                url: url.to_string(),
+                status,
+            }
+            .into());
+        }
+
+        response
+            .error_for_status()
+            .context(format!("Request to {} failed", url))
+    }
+
+    /// Exponential backoff with full jitter, so retries from different requests spread out
+    fn backoff(&self, attempt: u32) -> Duration {
+        let max_delay = Duration::from_millis(self.settings.max_delay_ms);
+        let delay = Duration::from_millis(self.settings.base_delay_ms)
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(max_delay);
+
+        delay.mul_f64(rand::thread_rng().gen_range(0.5..=1.0))
+    }
+
+    async fn wait_for_turn(&self, url: &url::Url) {
+        if self.settings.requests_per_second <= 0.0 {
+            return;
+        }
+
+        let host = url.host_str().unwrap_or_default().to_owned();
+        let interval = Duration::from_secs_f64(1.0 / self.settings.requests_per_second);
+
+        let wait = {
+            let mut next_request = self.next_request.lock().unwrap();
+            let now = Instant::now();
+            let scheduled = next_request
+                .get(&host)
+                .copied()
+                .filter(|next| *next > now)
+                .unwrap_or(now);
+            next_request.insert(host, scheduled + interval);
+            scheduled - now
+        };
+
+        if !wait.is_zero() {
+            log::trace!("Throttling request to {} for {}ms", url, wait.as_millis());
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+/// `Retry-After` is either a number of seconds or an http date
+fn parse_retry_after(value: &str) -> Option<Duration> {
+    if let Ok(seconds) = value.trim().parse() {
+        return Some(Duration::from_secs(seconds));
+    }
+
+    let date = DateTime::parse_from_rfc2822(value.trim()).ok()?;
+    Some(
+        (date.with_timezone(&Utc) - Utc::now())
+            .to_std()
+            .unwrap_or_default(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use rstest::rstest;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    use super::*;
+
+    /// Serves the given responses in order, one per connection
+    async fn serve(responses: Vec<&'static str>) -> (url::Url, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = url::Url::parse(&format!("http://{}/", listener.local_addr().unwrap())).unwrap();
+        let served = Arc::new(AtomicUsize::new(0));
+
+        let count = served.clone();
+        tokio::spawn(async move {
+            for response in responses {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut buf = [0; 1024];
+                let _ = stream.read(&mut buf).await;
+                stream.write_all(response.as_bytes()).await.unwrap();
+                count.fetch_add(1, Ordering::SeqCst);
+            }
+        });
+
+        (url, served)
+    }
+
+    const OK: &str = "HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\nok";
+    const UNAVAILABLE: &str =
+        "HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+    const TOO_MANY_REQUESTS: &str =
+        "HTTP/1.1 429 Too Many Requests\r\nRetry-After: 0\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+    const TOO_MANY_REQUESTS_LATER: &str =
+        "HTTP/1.1 429 Too Many Requests\r\nRetry-After: 120\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+    const NOT_FOUND: &str =
+        "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+
+    fn client(max_retries: u32) -> HttpClient {
+        HttpClient::new(reqwest::Client::new()).with_settings(HttpSettings {
+            requests_per_second: 0.0,
+            max_retries,
+            base_delay_ms: 1,
+            max_delay_ms: 10,
+        })
+    }
+
+    #[tokio::test]
+    async fn test_retries_transient_failures() {
+        let (url, served) = serve(vec![UNAVAILABLE, TOO_MANY_REQUESTS, OK]).await;
+
+        let response = client(3).get(&url).await.unwrap();
+        assert_eq!(response.text().await.unwrap(), "ok");
+        assert_eq!(served.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_typed_errors() {
+        let (url, _) = serve(vec![UNAVAILABLE]).await;
+        let err = client(0).get(&url).await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<HttpError>(),
+            Some(HttpError::Server { status, .. }) if *status == StatusCode::SERVICE_UNAVAILABLE
+        ));
+
+        let (url, _) = serve(vec![TOO_MANY_REQUESTS]).await;
+        let err = client(0).get(&url).await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<HttpError>(),
+            Some(HttpError::RateLimited { retry_after: Some(retry_after), .. }) if retry_after.is_zero()
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_long_retry_after_is_not_waited_for() {
+        let (url, served) = serve(vec![TOO_MANY_REQUESTS_LATER, OK]).await;
+
+        let start = Instant::now();
+        let err = client(3).get(&url).await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<HttpError>(),
+            Some(HttpError::RateLimited { retry_after: Some(retry_after), .. })
+                if *retry_after == Duration::from_secs(120)
+        ));
+        assert_eq!(served.load(Ordering::SeqCst), 1);
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[tokio::test]
+    async fn test_client_errors_are_not_retried() {
+        let (url, served) = serve(vec![NOT_FOUND, OK]).await;
+
+        let err = client(3).get(&url).await.unwrap_err();
+        assert!(err.downcast_ref::<HttpError>().is_none());
+        assert_eq!(served.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_rate_limit() {
+        let (url, _) = serve(vec![OK, OK, OK]).await;
+        let client = HttpClient::new(reqwest::Client::new()).with_settings(HttpSettings {
+            requests_per_second: 20.0,
+            ..Default::default()
+        });
+
+        let start = Instant::now();
+        for _ in 0..3 {
+            client.get(&url).await.unwrap();
+        }
+        // the first request goes out immediately, the other two wait 50ms each
+        assert!(start.elapsed() >= Duration::from_millis(100));
+    }
+
+    #[rstest]
+    #[case(2.0, 2.0)]
+    #[case(1e-300, MIN_REQUESTS_PER_SECOND)]
+    #[case(0.0, 0.0)]
+    fn test_clamp_requests_per_second(#[case] requests_per_second: f64, #[case] expected: f64) {
+        let settings = HttpSettings {
+            requests_per_second,
+            ..Default::default()
+        };
+        assert_eq!(settings.clamped().requests_per_second, expected);
+    }
+
+    #[tokio::test]
+    async fn test_tiny_rate_limit() {
+        let (url, _) = serve(vec![OK]).await;
+        // the interval between requests would overflow a Duration if it wasn't clamped
+        let client = HttpClient::new(reqwest::Client::new()).with_settings(HttpSettings {
+            requests_per_second: 1e-300,
+            ..Default::default()
+        });
+
+        client.get(&url).await.unwrap();
+    }
+
+    #[test]
+    fn test_backoff() {
+        let client = client(5);
+        for attempt in 0..5 {
+            let delay = client.backoff(attempt);
+            let expected = Duration::from_millis(2u64.pow(attempt)).min(Duration::from_millis(10));
+            assert!(delay >= expected / 2 && delay <= expected, "{:?}", delay);
+        }
+    }
+
+    #[rstest]
+    #[case("120", Some(Duration::from_secs(120)))]
+    #[case("Wed, 21 Oct 2015 07:28:00 GMT", Some(Duration::ZERO))]
+    #[case("soon", None)]
+    fn test_parse_retry_after(#[case] value: &str, #[case] expected: Option<Duration>) {
+        assert_eq!(parse_retry_after(value), expected);
+    }
+}
@@ -5,10 +5,15 @@ use crate::{app_service::AppService, utils::parse_pages_uri};
 
 pub mod app_service;
 mod commands;
+pub mod download_rule;
+pub mod favourite_uploader;
+pub mod http;
 pub mod library;
 pub mod metadata;
 pub mod metafile;
 pub mod reader;
+pub mod saved_search;
+pub mod search_group;
 pub mod settings;
 pub mod source;
 pub mod torrent;
@@ -34,6 +39,10 @@ pub fn run() {
             })
             .expect("Failed to create app service");
             app.manage(Mutex::new(app_service));
+            commands::spawn_background_poller(app.handle().clone());
+            commands::spawn_queue_promoter(app.handle().clone());
+            commands::spawn_seeding_enforcer(app.handle().clone());
+            commands::watch_unfinished_downloads(app.handle().clone());
             log::info!("Setup complete");
             Ok(())
         })
@@ -73,7 +82,23 @@ pub fn run() {
         .invoke_handler(tauri::generate_handler![
             commands::download,
             commands::search,
+            commands::search_all,
+            commands::search_grouped,
+            commands::get_torrent_details,
+            commands::list_source_files,
+            commands::get_download_files,
+            commands::update_download_files,
             commands::list_torrents,
+            commands::get_bandwidth_limits,
+            commands::set_global_bandwidth_limits,
+            commands::set_torrent_bandwidth_limits,
+            commands::get_seeding_policies,
+            commands::set_global_seeding_policy,
+            commands::set_torrent_seeding_policy,
+            commands::set_max_active_downloads,
+            commands::list_broken_torrents,
+            commands::repair_torrent,
+            commands::move_queued_download,
             commands::toggle_pause,
             commands::list_library,
             commands::delete,
@@ -84,7 +109,19 @@ pub fn run() {
             commands::update_library_entry_settings,
             commands::get_dimensions,
             commands::mark_as_read,
-            commands::update_library_entry_title
+            commands::update_library_entry_title,
+            commands::import_local,
+            commands::list_saved_searches,
+            commands::add_saved_search,
+            commands::remove_saved_search,
+            commands::list_download_rules,
+            commands::add_download_rule,
+            commands::remove_download_rule,
+            commands::dry_run_download_rule,
+            commands::list_favourite_uploaders,
+            commands::add_favourite_uploader,
+            commands::remove_favourite_uploader,
+            commands::uploader_feed
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
@@ -1,35 +1,52 @@
 use std::{
     collections::HashMap,
-    path::{Path, PathBuf},
+    path::{Component, Path, PathBuf},
 };
 
-use anyhow::{Context, Result};
+use anyhow::{anyhow, Context, Result};
 use log::info;
 use serde::{Deserialize, Serialize};
-use tokio::fs::{read_dir, remove_dir_all};
+use tokio::fs::{copy, create_dir_all, hard_link, read_dir, remove_dir_all, remove_file};
 
 use crate::{
     metadata::Metadata,
     metafile::{Metafile, ReadingProgress},
-    reader::Reader,
+    reader::{cbz_reader::validate_cbz, Reader},
     settings::ReaderSettings,
-    source::SourceMeta,
+    source::{release::ParsedRelease, SourceMeta},
     utils::read_files_from_dir,
 };
 
 #[derive(Serialize, Clone)]
 pub struct LibraryEntry {
     pub name: String,
+    pub release: ParsedRelease,
     pub metafile: Metafile,
     pub output_dir: PathBuf,
     pub files: Vec<String>,
 }
 
+/// An archive in a library entry that could not be read
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct InvalidArchive {
+    pub file: String,
+    pub error: String,
+}
+
 #[derive(Serialize, Deserialize, Clone)]
 pub struct LibraryEntrySettings {
     pub reader: ReaderSettings,
 }
 
+/// How imported files are brought into the library
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum ImportMode {
+    Copy,
+    /// Hard link the files, falling back to a copy when that is not possible
+    /// e.g. when the files are on a different filesystem
+    Link,
+}
+
 pub struct Library {
     entries: HashMap<String, LibraryEntry>,
 }
@@ -58,6 +75,7 @@ impl Library {
             metafile.source.id.clone(),
             LibraryEntry {
                 metafile,
+                release: ParsedRelease::parse(&name),
                 name,
                 files: Library::get_files(&output_dir).await?,
                 output_dir,
@@ -66,6 +84,48 @@ impl Library {
 
         Ok(())
     }
+
+    /// Bring `paths` into the library at `output_dir`.
+    /// `paths` is either a single folder, whose contents are imported, or a set of cbz files.
+    pub async fn import_files(
+        paths: &[PathBuf],
+        output_dir: &Path,
+        mode: ImportMode,
+    ) -> Result<()> {
+        if output_dir.exists() {
+            return Err(anyhow!(
+                "Library entry already exists at {}",
+                output_dir.display()
+            ));
+        }
+
+        match paths {
+            [dir] if dir.is_dir() => {
+                log::info!("Importing folder {}", dir.display());
+                import_dir(dir, output_dir, mode).await
+            }
+            [] => Err(anyhow!("Nothing to import")),
+            files => {
+                if let Some(file) = files.iter().find(|file| !is_cbz(file)) {
+                    return Err(anyhow!(
+                        "Only cbz files can be imported: {}",
+                        file.display()
+                    ));
+                }
+
+                create_dir_all(output_dir).await?;
+                for file in files {
+                    let filename = file
+                        .file_name()
+                        .context(format!("Invalid file to import: {}", file.display()))?;
+                    log::info!("Importing {}", file.display());
+                    import_file(file, &output_dir.join(filename), mode).await?;
+                }
+                Ok(())
+            }
+        }
+    }
+
     pub async fn get_entry(&mut self, id: &str) -> Option<LibraryEntry> {
         self.entries.get(id).cloned()
     }
@@ -99,6 +159,7 @@ impl Library {
         entry.output_dir = new_output_dir;
 
         entry.name = title.to_owned();
+        entry.release = ParsedRelease::parse(title);
         entry.metafile.metadata = metadata;
         entry.metafile.write(&entry.output_dir).await
     }
@@ -125,13 +186,7 @@ impl Library {
                 Err(e) => {
                     log::warn!("Failed to read metadata for {}: {e}", dir.path().display());
                     log::warn!("Creating placeholder metafile.");
-                    let metafile = Metafile::new(
-                        SourceMeta {
-                            id: "".into(),
-                            provider: crate::source::Sources::Nyaa,
-                        },
-                        None,
-                    );
+                    let metafile = Metafile::new(SourceMeta::local(), None);
                     metafile
                         .write(&dir.path())
                         .await
@@ -140,10 +195,12 @@ impl Library {
                 }
             };
 
+            let name = dir.file_name().to_string_lossy().to_string();
             library.insert(
                 metafile.source.id.clone(),
                 LibraryEntry {
-                    name: dir.file_name().to_string_lossy().to_string(),
+                    release: ParsedRelease::parse(&name),
+                    name,
                     metafile,
                     output_dir: dir.path(),
                     files: Library::get_files(&dir.path()).await?,
@@ -167,6 +224,90 @@ impl Library {
         Ok(())
     }
 
+    /// Pick up files added to or removed from the entry's folder, returning the ones that are new
+    pub async fn refresh_files(&mut self, id: &str) -> Result<Vec<String>> {
+        let entry = self
+            .entries
+            .get_mut(id)
+            .context(format!("Missing library entry for {}", id))?;
+
+        let files = Library::get_files(&entry.output_dir).await?;
+        let new_files = files
+            .iter()
+            .filter(|file| !entry.files.contains(file))
+            .cloned()
+            .collect();
+        entry.files = files;
+        Ok(new_files)
+    }
+
+    /// Read through the cbz `files` in `output_dir`, returning the ones that are corrupt or incomplete.
+    /// Doesn't touch the library, so it can run without holding on to it.
+    pub async fn validate_archives(
+        output_dir: &Path,
+        files: Vec<String>,
+    ) -> Result<Vec<InvalidArchive>> {
+        let archives: Vec<(String, PathBuf)> = files
+            .into_iter()
+            .map(|file| {
+                let path = output_dir.join(&file);
+                (file, path)
+            })
+            .filter(|(_, path)| is_cbz(path))
+            .collect();
+
+        tokio::task::spawn_blocking(move || {
+            archives
+                .into_iter()
+                .filter_map(|(file, path)| match validate_cbz(&path) {
+                    Ok(_) => None,
+                    Err(err) => {
+                        log::warn!("Invalid archive {}: {:#}", path.display(), err);
+                        Some(InvalidArchive {
+                            file,
+                            error: format!("{:#}", err),
+                        })
+                    }
+                })
+                .collect()
+        })
+        .await
+        .context("Failed to validate archives")
+    }
+
+    pub async fn update_metadata(&mut self, id: &str, metadata: Option<Metadata>) -> Result<()> {
+        let entry = self
+            .entries
+            .get_mut(id)
+            .context(format!("Missing library entry for {}", id))?;
+
+        entry.metafile.metadata = metadata;
+        entry.metafile.write(&entry.output_dir).await
+    }
+
+    /// Delete `paths`, relative to the entry's folder, skipping any that were never written
+    pub async fn remove_files(&mut self, id: &str, paths: &[String]) -> Result<()> {
+        let output_dir = self
+            .entries
+            .get(id)
+            .context(format!("Missing library entry for {}", id))?
+            .output_dir
+            .clone();
+
+        for path in paths {
+            let path = output_dir.join(path);
+            if path.is_file() {
+                log::info!("Removing {}", path.display());
+                remove_file(&path)
+                    .await
+                    .context(format!("Failed to remove {}", path.display()))?;
+            }
+        }
+
+        self.refresh_files(id).await?;
+        Ok(())
+    }
+
     pub async fn update_reading_progress(
         &mut self,
         id: &str,
@@ -284,3 +425,217 @@ impl Library {
             .await
     }
 }
+
+/// Checks that `name` can be used as the folder of a library entry,
+/// a single plain path component that can't point outside the library
+pub fn entry_dir_name(name: &str) -> Result<&str> {
+    let name = name.trim();
+    let mut components = Path::new(name).components();
+    match (components.next(), components.next()) {
+        (Some(Component::Normal(_)), None) if !name.contains(['/', '\\', ':']) => Ok(name),
+        _ => Err(anyhow!("Invalid name for a library entry: {:?}", name)),
+    }
+}
+
+fn is_cbz(path: &Path) -> bool {
+    path.is_file()
+        && path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("cbz"))
+}
+
+async fn import_file(src: &Path, dst: &Path, mode: ImportMode) -> Result<()> {
+    if mode == ImportMode::Link {
+        match hard_link(src, dst).await {
+            Ok(()) => return Ok(()),
+            Err(err) => log::warn!(
+                "Failed to link {} ({}), copying instead",
+                src.display(),
+                err
+            ),
+        }
+    }
+
+    copy(src, dst)
+        .await
+        .context(format!("Failed to copy {}", src.display()))?;
+    Ok(())
+}
+
+async fn import_dir(src: &Path, dst: &Path, mode: ImportMode) -> Result<()> {
+    let mut pending = vec![(src.to_path_buf(), dst.to_path_buf())];
+
+    while let Some((src, dst)) = pending.pop() {
+        create_dir_all(&dst).await?;
+
+        let mut children = read_dir(&src).await?;
+        while let Some(child) = children.next_entry().await? {
+            let target = dst.join(child.file_name());
+            if child.file_type().await?.is_dir() {
+                pending.push((child.path(), target));
+            } else {
+                import_file(&child.path(), &target, mode).await?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+    use tempdir::TempDir;
+
+    use super::*;
+
+    fn touch(path: &Path) {
+        std::fs::write(path, path.to_string_lossy().as_bytes()).unwrap();
+    }
+
+    #[rstest]
+    #[case("Oshi no Ko", Some("Oshi no Ko"))]
+    #[case("  Frieren ", Some("Frieren"))]
+    #[case("Fate/Zero", None)]
+    #[case("..\\Frieren", None)]
+    #[case("..", None)]
+    #[case(".", None)]
+    #[case("/tmp", None)]
+    #[case("C:\\Users", None)]
+    #[case("C:Frieren", None)]
+    #[case("", None)]
+    fn test_entry_dir_name(#[case] name: &str, #[case] expected: Option<&str>) {
+        assert_eq!(entry_dir_name(name).ok(), expected);
+    }
+
+    #[tokio::test]
+    async fn test_import_folder() {
+        let dir = TempDir::new("library").unwrap();
+        let src = dir.path().join("Oshi no Ko");
+        std::fs::create_dir_all(src.join("extras")).unwrap();
+        touch(&src.join("v01.cbz"));
+        touch(&src.join("extras/cover.png"));
+
+        let output_dir = dir.path().join("library/Oshi no Ko");
+        Library::import_files(std::slice::from_ref(&src), &output_dir, ImportMode::Copy)
+            .await
+            .unwrap();
+
+        assert!(output_dir.join("v01.cbz").is_file());
+        assert!(output_dir.join("extras/cover.png").is_file());
+        // copies leave the originals in place
+        assert!(src.join("v01.cbz").is_file());
+
+        // importing over an existing entry is refused
+        assert!(Library::import_files(&[src], &output_dir, ImportMode::Copy)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_import_cbz_files() {
+        let dir = TempDir::new("library").unwrap();
+        let files = vec![dir.path().join("v01.cbz"), dir.path().join("v02.CBZ")];
+        files.iter().for_each(|file| touch(file));
+
+        let output_dir = dir.path().join("library/Frieren");
+        Library::import_files(&files, &output_dir, ImportMode::Link)
+            .await
+            .unwrap();
+
+        let mut imported = read_files_from_dir(&output_dir).await.unwrap();
+        imported.sort();
+        assert_eq!(imported, vec!["v01.cbz", "v02.CBZ"]);
+
+        let not_cbz = dir.path().join("notes.txt");
+        touch(&not_cbz);
+        assert!(Library::import_files(
+            &[not_cbz],
+            &dir.path().join("library/Notes"),
+            ImportMode::Copy
+        )
+        .await
+        .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_remove_files() {
+        let dir = TempDir::new("library").unwrap();
+        let entry_dir = dir.path().join("Frieren");
+        std::fs::create_dir(&entry_dir).unwrap();
+        touch(&entry_dir.join("v01.cbz"));
+        touch(&entry_dir.join("v02.cbz"));
+
+        let mut library = Library::new(dir.path()).await;
+        let id = library.get_entries()[0].metafile.source.id.clone();
+
+        library
+            .remove_files(&id, &["v01.cbz".to_owned(), "v03.cbz".to_owned()])
+            .await
+            .unwrap();
+        assert!(!entry_dir.join("v01.cbz").exists());
+        assert_eq!(library.get_entries()[0].files, vec!["v02.cbz"]);
+
+        touch(&entry_dir.join("v03.cbz"));
+        assert_eq!(library.refresh_files(&id).await.unwrap(), vec!["v03.cbz"]);
+        assert_eq!(library.get_entries()[0].files, vec!["v02.cbz", "v03.cbz"]);
+    }
+
+    #[tokio::test]
+    async fn test_validate_archives() {
+        let dir = TempDir::new("library").unwrap();
+        let entry_dir = dir.path().join("Frieren");
+        std::fs::create_dir(&entry_dir).unwrap();
+
+        let mut zip =
+            zip::ZipWriter::new(std::fs::File::create(entry_dir.join("v01.cbz")).unwrap());
+        zip.start_file("001.png", zip::write::SimpleFileOptions::default())
+            .unwrap();
+        std::io::Write::write_all(&mut zip, b"page").unwrap();
+        zip.finish().unwrap();
+        touch(&entry_dir.join("v02.cbz"));
+        touch(&entry_dir.join("notes.txt"));
+
+        let invalid = Library::validate_archives(
+            &entry_dir,
+            vec![
+                "v01.cbz".to_owned(),
+                "v02.cbz".to_owned(),
+                "notes.txt".to_owned(),
+            ],
+        )
+        .await
+        .unwrap();
+        let files: Vec<&str> = invalid
+            .iter()
+            .map(|archive| archive.file.as_str())
+            .collect();
+        assert_eq!(files, vec!["v02.cbz"]);
+    }
+
+    #[tokio::test]
+    async fn test_placeholder_ids_are_unique() {
+        let dir = TempDir::new("library").unwrap();
+        std::fs::create_dir(dir.path().join("first")).unwrap();
+        std::fs::create_dir(dir.path().join("second")).unwrap();
+
+        let library = Library::new(dir.path()).await;
+        assert_eq!(library.get_entries().len(), 2);
+
+        // the generated id is persisted so it survives a restart
+        let reloaded = Library::new(dir.path()).await;
+        let mut ids: Vec<String> = library
+            .get_entries()
+            .into_iter()
+            .map(|entry| entry.metafile.source.id)
+            .collect();
+        let mut reloaded_ids: Vec<String> = reloaded
+            .get_entries()
+            .into_iter()
+            .map(|entry| entry.metafile.source.id)
+            .collect();
+        ids.sort();
+        reloaded_ids.sort();
+        assert_eq!(ids, reloaded_ids);
+    }
+}
@@ -1,7 +1,10 @@
 use std::path::Path;
 
 use super::Metadata;
-use crate::utils::{download_file_from_url, unpack_tarball};
+use crate::{
+    http::HttpClient,
+    utils::{download_file_from_url, unpack_tarball},
+};
 use anyhow::{Context, Result};
 use async_trait::async_trait;
 use sqlx::{query, query_as, sqlite::SqlitePoolOptions, SqlitePool};
@@ -65,7 +68,7 @@ impl MangabakaMetadata {
 }
 
 impl Mangabaka {
-    pub async fn setup(client: &reqwest::Client, output_dir: &Path) -> Result<Self> {
+    pub async fn setup(client: &HttpClient, output_dir: &Path) -> Result<Self> {
         let db_filename = "series.sqlite";
         let db_path = output_dir.join(db_filename);
         if !output_dir.exists() {
@@ -106,7 +109,7 @@ impl Mangabaka {
         Ok(Mangabaka::new(pool))
     }
 
-    async fn download_db(client: &reqwest::Client, output_dir: &Path) -> Result<()> {
+    async fn download_db(client: &HttpClient, output_dir: &Path) -> Result<()> {
         let filename = "series.sqlite.tar.gz";
         let download_url = Url::parse(MANGABAKA_URL)?
             .join("database/")?
@@ -85,3 +85,20 @@ impl Reader for CBZReader {
         Ok(archive.len())
     }
 }
+
+/// Read every entry of the archive at `path` so truncated or corrupt files are caught,
+/// returning the number of entries
+pub fn validate_cbz(path: &Path) -> Result<usize> {
+    let file = std::fs::File::open(path)?;
+    let mut archive = zip::ZipArchive::new(file)?;
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i)?;
+        // the checksum is verified once an entry has been read to the end
+        std::io::copy(&mut entry, &mut std::io::sink()).context(format!(
+            "Corrupt entry {} in {}",
+            entry.name(),
+            path.display()
+        ))?;
+    }
+    Ok(archive.len())
+}
@@ -0,0 +1,232 @@
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{from_str, to_vec};
+use tokio::{
+    fs::{read_to_string, File},
+    io::AsyncWriteExt,
+};
+use uuid::Uuid;
+
+use crate::source::{MediaInfo, SearchQuery};
+
+const SAVED_SEARCHES_FILE: &str = "saved_searches.json";
+
+/// Only the most recent ids are kept, older results won't show up in a search again
+const MAX_SEEN_IDS: usize = 1000;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SavedSearch {
+    pub id: String,
+    pub name: String,
+    pub query: SearchQuery,
+    /// Ids of results that have already been seen, most recent first
+    pub seen_ids: Vec<String>,
+    pub last_checked: Option<DateTime<Utc>>,
+}
+
+/// Results for a saved search that weren't there the last time it was checked
+#[derive(Serialize, Clone, Debug)]
+pub struct NewReleases {
+    pub search_id: String,
+    pub name: String,
+    pub releases: Vec<MediaInfo>,
+}
+
+pub struct SavedSearches {
+    path: PathBuf,
+    searches: Vec<SavedSearch>,
+}
+
+impl SavedSearches {
+    /// Read the saved searches from `dir`, starting empty if none have been saved yet
+    pub async fn read(dir: &Path) -> Result<Self> {
+        let path = dir.join(SAVED_SEARCHES_FILE);
+        let searches = if path.exists() {
+            let content = read_to_string(&path).await?;
+            from_str(&content).context(format!("Invalid saved searches: {}", path.display()))?
+        } else {
+            vec![]
+        };
+
+        log::info!("Found {} saved searches", searches.len());
+        Ok(SavedSearches { path, searches })
+    }
+
+    async fn write(&self) -> Result<()> {
+        let mut file = File::create(&self.path).await?;
+        file.write_all(to_vec(&self.searches)?.as_slice()).await?;
+        file.flush().await?;
+        log::trace!(
+            "Successfully wrote saved searches to {}",
+            self.path.display()
+        );
+        Ok(())
+    }
+
+    pub fn list(&self) -> Vec<SavedSearch> {
+        self.searches.clone()
+    }
+
+    /// Save `query`, treating `current_results` as already seen
+    /// so only releases after this point are reported as new
+    pub async fn add(
+        &mut self,
+        name: &str,
+        query: SearchQuery,
+        current_results: &[MediaInfo],
+    ) -> Result<SavedSearch> {
+        let search = SavedSearch {
+            id: Uuid::new_v4().to_string(),
+            name: name.to_owned(),
+            query,
+            seen_ids: current_results.iter().map(|info| info.id.clone()).collect(),
+            last_checked: Some(Utc::now()),
+        };
+
+        log::info!("Saving search \"{}\"", name);
+        self.searches.push(search.clone());
+        self.write().await?;
+        Ok(search)
+    }
+
+    pub async fn remove(&mut self, id: &str) -> Result<()> {
+        let index = self
+            .searches
+            .iter()
+            .position(|search| search.id == id)
+            .context(format!("Missing saved search for {}", id))?;
+
+        let search = self.searches.remove(index);
+        log::info!("Removed saved search \"{}\"", search.name);
+        self.write().await
+    }
+
+    /// Mark `results` as seen for the search with `id`, returning the ones that are new
+    pub async fn record_results(
+        &mut self,
+        id: &str,
+        results: Vec<MediaInfo>,
+    ) -> Result<NewReleases> {
+        let search = self
+            .searches
+            .iter_mut()
+            .find(|search| search.id == id)
+            .context(format!("Missing saved search for {}", id))?;
+
+        let seen: HashSet<&String> = search.seen_ids.iter().collect();
+        let releases: Vec<MediaInfo> = results
+            .into_iter()
+            .filter(|info| !seen.contains(&info.id))
+            .collect();
+
+        search.seen_ids = releases
+            .iter()
+            .map(|info| info.id.clone())
+            .chain(search.seen_ids.drain(..))
+            .take(MAX_SEEN_IDS)
+            .collect();
+        search.last_checked = Some(Utc::now());
+
+        let new_releases = NewReleases {
+            search_id: search.id.clone(),
+            name: search.name.clone(),
+            releases,
+        };
+        self.write().await?;
+
+        Ok(new_releases)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempdir::TempDir;
+
+    use crate::source::{Category, FileSize, ReleaseStatus};
+
+    use super::*;
+
+    fn media_info(id: &str) -> MediaInfo {
+        MediaInfo {
+            id: id.to_owned(),
+            category: Category::Manga,
+            title: id.to_owned(),
+            size: FileSize::from_bytes(0),
+            timestamp: DateTime::default(),
+            seeders: 0,
+            leechers: 0,
+            completed: 0,
+            info_hash: "".to_owned(),
+            status: ReleaseStatus::Normal,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_new_releases() {
+        let dir = TempDir::new("saved_searches").unwrap();
+        let mut saved = SavedSearches::read(dir.path()).await.unwrap();
+
+        let search = saved
+            .add(
+                "Oshi no Ko",
+                SearchQuery {
+                    text: "oshi no ko".to_owned(),
+                    ..Default::default()
+                },
+                &[media_info("1"), media_info("2")],
+            )
+            .await
+            .unwrap();
+
+        let new_releases = saved
+            .record_results(
+                &search.id,
+                vec![media_info("3"), media_info("1"), media_info("2")],
+            )
+            .await
+            .unwrap();
+        let ids: Vec<&str> = new_releases
+            .releases
+            .iter()
+            .map(|info| info.id.as_str())
+            .collect();
+        assert_eq!(ids, vec!["3"]);
+
+        // nothing is new the second time around
+        let new_releases = saved
+            .record_results(&search.id, vec![media_info("3"), media_info("1")])
+            .await
+            .unwrap();
+        assert!(new_releases.releases.is_empty());
+
+        // the seen ids are persisted
+        let reloaded = SavedSearches::read(dir.path()).await.unwrap();
+        assert_eq!(reloaded.list()[0].seen_ids, vec!["3", "1", "2"]);
+    }
+
+    #[tokio::test]
+    async fn test_remove() {
+        let dir = TempDir::new("saved_searches").unwrap();
+        let mut saved = SavedSearches::read(dir.path()).await.unwrap();
+
+        let search = saved
+            .add("Frieren", SearchQuery::default(), &[])
+            .await
+            .unwrap();
+        saved.remove(&search.id).await.unwrap();
+
+        assert!(saved.list().is_empty());
+        assert!(saved.remove(&search.id).await.is_err());
+        assert!(SavedSearches::read(dir.path())
+            .await
+            .unwrap()
+            .list()
+            .is_empty());
+    }
+}
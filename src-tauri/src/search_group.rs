@@ -0,0 +1,200 @@
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+use crate::{
+    app_service::SearchResult,
+    metadata::Metadata,
+    source::{release::NumberRange, PaginationInfo},
+};
+
+/// Releases of the same series within a page of search results
+#[derive(Serialize)]
+pub struct SearchGroup {
+    pub title: String,
+    pub metadata: Option<Metadata>,
+    /// Volumes covered by any release in the group, merged into sorted, disjoint ranges
+    pub volumes: Vec<NumberRange>,
+    /// Index into `releases` of the release with the most seeders
+    pub best_release: usize,
+    pub releases: Vec<SearchResult>,
+}
+
+#[derive(Serialize)]
+pub struct GroupedSearchResponse {
+    pub groups: Vec<SearchGroup>,
+    pub pagination: PaginationInfo,
+}
+
+#[derive(PartialEq, Eq, Hash)]
+enum GroupKey {
+    Metadata(i64),
+    Title(String),
+}
+
+/// Cluster `results` by their matched metadata, or by `normalize_title` when nothing matched.
+/// Groups keep the order their first release appeared in, and so do releases within a group.
+pub fn group_results(
+    results: Vec<SearchResult>,
+    normalize_title: impl Fn(&SearchResult) -> String,
+) -> Vec<SearchGroup> {
+    let mut groups: Vec<SearchGroup> = vec![];
+    let mut index: HashMap<GroupKey, usize> = HashMap::new();
+
+    for result in results {
+        let key = match &result.metadata {
+            Some(metadata) => GroupKey::Metadata(metadata.id),
+            None => GroupKey::Title(normalize_title(&result)),
+        };
+
+        match index.get(&key) {
+            Some(&i) => groups[i].releases.push(result),
+            None => {
+                index.insert(key, groups.len());
+                groups.push(SearchGroup {
+                    title: match &result.metadata {
+                        Some(metadata) => metadata.title.clone(),
+                        None => result.release.title.clone(),
+                    },
+                    metadata: result.metadata.clone(),
+                    volumes: vec![],
+                    best_release: 0,
+                    releases: vec![result],
+                });
+            }
+        }
+    }
+
+    for group in groups.iter_mut() {
+        group.volumes = merge_ranges(
+            group
+                .releases
+                .iter()
+                .filter_map(|result| result.release.volumes)
+                .collect(),
+        );
+        group.best_release = group
+            .releases
+            .iter()
+            .enumerate()
+            // ties go to the earlier release
+            .max_by_key(|(i, result)| (result.media_info.seeders, std::cmp::Reverse(*i)))
+            .map(|(i, _)| i)
+            .unwrap_or_default();
+    }
+
+    groups
+}
+
+/// Sort `ranges` and merge any that overlap or are next to each other
+fn merge_ranges(mut ranges: Vec<NumberRange>) -> Vec<NumberRange> {
+    ranges.sort_by_key(|range| (range.start, range.end));
+
+    let mut merged: Vec<NumberRange> = vec![];
+    for range in ranges {
+        match merged.last_mut() {
+            Some(last) if range.start <= last.end.saturating_add(1) => {
+                last.end = last.end.max(range.end);
+            }
+            _ => merged.push(range),
+        }
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::DateTime;
+    use rstest::rstest;
+
+    use crate::source::{
+        release::ParsedRelease, Category, FileSize, MediaInfo, ReleaseStatus, Sources,
+    };
+
+    use super::*;
+
+    fn search_result(
+        id: &str,
+        title: &str,
+        seeders: u32,
+        metadata_id: Option<i64>,
+    ) -> SearchResult {
+        SearchResult {
+            provider: Sources::Nyaa,
+            release: ParsedRelease::parse(title),
+            media_info: MediaInfo {
+                id: id.to_owned(),
+                category: Category::Manga,
+                title: title.to_owned(),
+                size: FileSize::from_bytes(0),
+                timestamp: DateTime::default(),
+                seeders,
+                leechers: 0,
+                completed: 0,
+                info_hash: "".to_owned(),
+                status: ReleaseStatus::Normal,
+            },
+            metadata: metadata_id.map(|id| Metadata {
+                id,
+                title: format!("Series {}", id),
+                cover: None,
+                cover_raw: None,
+                authors: None,
+                artists: None,
+                description: None,
+                year: None,
+                tags: None,
+                media_type: "manga".to_owned(),
+                status: "ongoing".to_owned(),
+                genres: None,
+            }),
+        }
+    }
+
+    #[test]
+    fn test_group_results() {
+        let results = vec![
+            search_result("1", "Oshi no Ko v01 (Digital) (1r0n)", 10, Some(1)),
+            search_result("2", "Blue Period v03 (Digital)", 5, None),
+            search_result("3", "Oshi no Ko v02-04 (Digital) (LuCaZ)", 30, Some(1)),
+            search_result("4", "Blue Period v01-02 (Digital)", 5, None),
+            search_result("5", "Dandadan v01 (Digital)", 1, None),
+        ];
+
+        let groups = group_results(results, |result| result.release.title.to_lowercase());
+
+        let titles: Vec<&str> = groups.iter().map(|group| group.title.as_str()).collect();
+        assert_eq!(titles, vec!["Series 1", "Blue Period", "Dandadan"]);
+
+        let oshi_no_ko = &groups[0];
+        assert_eq!(oshi_no_ko.releases.len(), 2);
+        assert_eq!(oshi_no_ko.volumes, vec![NumberRange { start: 1, end: 4 }]);
+        assert_eq!(
+            oshi_no_ko.releases[oshi_no_ko.best_release].media_info.id,
+            "3"
+        );
+
+        let blue_period = &groups[1];
+        assert_eq!(blue_period.volumes, vec![NumberRange { start: 1, end: 3 }]);
+        // ties go to the first release
+        assert_eq!(
+            blue_period.releases[blue_period.best_release].media_info.id,
+            "2"
+        );
+    }
+
+    #[rstest]
+    #[case(vec![], vec![])]
+    #[case(vec![(5, 6), (1, 2)], vec![(1, 2), (5, 6)])]
+    #[case(vec![(3, 4), (1, 2), (5, 5)], vec![(1, 5)])]
+    #[case(vec![(1, 10), (2, 3)], vec![(1, 10)])]
+    fn test_merge_ranges(#[case] ranges: Vec<(u32, u32)>, #[case] expected: Vec<(u32, u32)>) {
+        let to_ranges = |ranges: Vec<(u32, u32)>| -> Vec<NumberRange> {
+            ranges
+                .into_iter()
+                .map(|(start, end)| NumberRange { start, end })
+                .collect()
+        };
+        assert_eq!(merge_ranges(to_ranges(ranges)), to_ranges(expected));
+    }
+}
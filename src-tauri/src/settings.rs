@@ -1,4 +1,26 @@
+use std::{collections::HashMap, path::Path, time::Duration};
+
+use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
+use serde_json::{from_str, to_vec_pretty};
+use tokio::{
+    fs::{read_to_string, File},
+    io::AsyncWriteExt,
+};
+
+use crate::{
+    http::HttpSettings,
+    source::nyaa::{NyaaBackend, DEFAULT_VIEW_TTL, NYAA_URL},
+    torrent::{
+        queue::DEFAULT_MAX_ACTIVE, rqbit_service::DEFAULT_MAGNET_TIMEOUT, BandwidthLimits,
+        SeedingPolicy,
+    },
+};
+
+const SETTINGS_FILE: &str = "settings.json";
+
+/// Saved searches hit the site on every check, so they aren't checked more often than this
+pub const MIN_POLL_INTERVAL_SECS: u64 = 60;
 
 #[derive(Serialize, Deserialize, Clone)]
 pub enum ReaderLayout {
@@ -13,3 +35,212 @@ pub struct ReaderSettings {
     pub background_color: Option<String>,
     pub layout: Option<ReaderLayout>,
 }
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct NyaaSettings {
+    pub base_url: String,
+    /// Tried in order whenever the base url fails
+    pub mirrors: Vec<String>,
+    pub backend: NyaaBackend,
+}
+
+impl Default for NyaaSettings {
+    fn default() -> Self {
+        NyaaSettings {
+            base_url: NYAA_URL.to_owned(),
+            mirrors: vec![],
+            backend: NyaaBackend::default(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct CacheSettings {
+    /// How long fetched pages are reused for, in seconds
+    pub ttl_secs: u64,
+    /// How long view pages are reused for, in seconds, they show live seeder counts
+    pub view_ttl_secs: u64,
+    pub max_entries: usize,
+}
+
+impl Default for CacheSettings {
+    fn default() -> Self {
+        CacheSettings {
+            ttl_secs: 5 * 60,
+            view_ttl_secs: DEFAULT_VIEW_TTL.as_secs(),
+            max_entries: 200,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct SavedSearchSettings {
+    /// How often saved searches are checked for new releases, in seconds,
+    /// raised to `MIN_POLL_INTERVAL_SECS` when lower
+    pub poll_interval_secs: u64,
+}
+
+impl SavedSearchSettings {
+    pub fn poll_interval(&self) -> Duration {
+        Duration::from_secs(self.poll_interval_secs.max(MIN_POLL_INTERVAL_SECS))
+    }
+}
+
+impl Default for SavedSearchSettings {
+    fn default() -> Self {
+        SavedSearchSettings {
+            poll_interval_secs: 30 * 60,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct BandwidthSettings {
+    pub global: BandwidthLimits,
+    /// Overrides keyed by source id, applied on top of the global limits
+    pub torrents: HashMap<String, BandwidthLimits>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct SeedingSettings {
+    pub global: SeedingPolicy,
+    /// Overrides keyed by source id, used instead of the global policy
+    pub torrents: HashMap<String, SeedingPolicy>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct TorrentSettings {
+    /// How long resolving a magnet can take before falling back to the .torrent file, in seconds
+    pub magnet_timeout_secs: u64,
+}
+
+impl Default for TorrentSettings {
+    fn default() -> Self {
+        TorrentSettings {
+            magnet_timeout_secs: DEFAULT_MAGNET_TIMEOUT.as_secs(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct QueueSettings {
+    /// Downloads past this many wait in the queue until one finishes
+    pub max_active_downloads: usize,
+}
+
+impl Default for QueueSettings {
+    fn default() -> Self {
+        QueueSettings {
+            max_active_downloads: DEFAULT_MAX_ACTIVE,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct AppSettings {
+    pub nyaa: NyaaSettings,
+    pub cache: CacheSettings,
+    pub http: HttpSettings,
+    pub saved_searches: SavedSearchSettings,
+    pub torrent: TorrentSettings,
+    pub bandwidth: BandwidthSettings,
+    pub queue: QueueSettings,
+    pub seeding: SeedingSettings,
+}
+
+impl AppSettings {
+    /// Read the settings file from `dir`, using the defaults if it does not exist yet
+    pub async fn read(dir: &Path) -> Result<AppSettings> {
+        let settings_path = dir.join(SETTINGS_FILE);
+        if !settings_path.exists() {
+            log::info!(
+                "No settings found at {}, using defaults",
+                settings_path.display()
+            );
+            return Ok(AppSettings::default());
+        }
+
+        let content = read_to_string(&settings_path).await?;
+        from_str(&content).context(format!(
+            "Invalid settings file: {}",
+            settings_path.display()
+        ))
+    }
+
+    pub async fn write(&self, dir: &Path) -> Result<()> {
+        let settings_path = dir.join(SETTINGS_FILE);
+
+        let mut file = File::create(&settings_path).await?;
+        file.write_all(to_vec_pretty(&self)?.as_slice()).await?;
+        file.flush().await?;
+        log::trace!("Successfully wrote settings to {}", settings_path.display());
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempdir::TempDir;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_settings_round_trip() {
+        let dir = TempDir::new("settings").unwrap();
+        assert_eq!(
+            AppSettings::read(dir.path()).await.unwrap(),
+            AppSettings::default()
+        );
+
+        let mut settings = AppSettings::default();
+        settings.nyaa.mirrors = vec!["http://localhost:8080/".to_owned()];
+        settings.nyaa.backend = NyaaBackend::Rss;
+        settings.bandwidth.global.download_bps = Some(1024 * 1024);
+        settings.bandwidth.torrents.insert(
+            "1990813".to_owned(),
+            BandwidthLimits {
+                upload_bps: Some(64 * 1024),
+                download_bps: None,
+            },
+        );
+        settings.seeding.global = SeedingPolicy::Ratio { ratio: 1.5 };
+        settings
+            .seeding
+            .torrents
+            .insert("1990813".to_owned(), SeedingPolicy::Never);
+        settings.write(dir.path()).await.unwrap();
+
+        assert_eq!(AppSettings::read(dir.path()).await.unwrap(), settings);
+    }
+
+    #[test]
+    fn test_partial_settings() {
+        let settings: AppSettings =
+            from_str(r#"{"nyaa": {"base_url": "http://localhost/"}}"#).unwrap();
+        assert_eq!(settings.nyaa.base_url, "http://localhost/");
+        assert_eq!(settings.nyaa.backend, NyaaBackend::Html);
+    }
+
+    #[test]
+    fn test_poll_interval_minimum() {
+        let settings: AppSettings =
+            from_str(r#"{"saved_searches": {"poll_interval_secs": 0}}"#).unwrap();
+        assert_eq!(
+            settings.saved_searches.poll_interval(),
+            Duration::from_secs(MIN_POLL_INTERVAL_SECS)
+        );
+        assert_eq!(
+            AppSettings::default().saved_searches.poll_interval(),
+            Duration::from_secs(30 * 60)
+        );
+    }
+}
@@ -1,14 +1,32 @@
-use anyhow::Result;
+use anyhow::{anyhow, Context, Error, Result};
 use async_trait::async_trait;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use std::path::{Path, PathBuf};
+use std::{
+    path::{Path, PathBuf},
+    str::FromStr,
+};
 
+use uuid::Uuid;
+
+use crate::{
+    source::{
+        nyaa::{category::NyaaCategory, query_params::NyaaFilter},
+        release::ParsedRelease,
+    },
+    torrent::TorrentFile,
+};
+
+pub mod cache;
 pub mod nyaa;
+pub mod registry;
+pub mod release;
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Sources {
     Nyaa,
+    /// Content imported from the local filesystem rather than downloaded
+    Local,
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -17,19 +35,142 @@ pub struct SourceMeta {
     pub provider: Sources,
 }
 
-#[derive(Debug, Serialize)]
-#[serde(tag = "unit", content = "size")]
-pub enum FileSize {
-    MiB(f32),
-    GiB(f32),
+impl SourceMeta {
+    /// Source for local content, which has no id of its own so one is generated
+    pub fn local() -> Self {
+        SourceMeta {
+            id: Uuid::new_v4().to_string(),
+            provider: Sources::Local,
+        }
+    }
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SizeUnit {
+    Bytes,
+    KiB,
+    MiB,
+    GiB,
+    TiB,
+}
+
+impl SizeUnit {
+    const UNITS: [SizeUnit; 5] = [
+        SizeUnit::Bytes,
+        SizeUnit::KiB,
+        SizeUnit::MiB,
+        SizeUnit::GiB,
+        SizeUnit::TiB,
+    ];
+
+    pub fn num_bytes(&self) -> u64 {
+        match self {
+            SizeUnit::Bytes => 1,
+            SizeUnit::KiB => 1 << 10,
+            SizeUnit::MiB => 1 << 20,
+            SizeUnit::GiB => 1 << 30,
+            SizeUnit::TiB => 1 << 40,
+        }
+    }
+}
+
+impl FromStr for SizeUnit {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "B" | "Bytes" => Ok(SizeUnit::Bytes),
+            "KiB" => Ok(SizeUnit::KiB),
+            "MiB" => Ok(SizeUnit::MiB),
+            "GiB" => Ok(SizeUnit::GiB),
+            "TiB" => Ok(SizeUnit::TiB),
+            unit => Err(anyhow!("Unrecognized file size unit {}", unit)),
+        }
+    }
+}
+
+/// A file size as displayed by the source along with its size in bytes
+#[derive(Debug, Serialize, Clone, Copy, PartialEq)]
+pub struct FileSize {
+    pub unit: SizeUnit,
+    pub size: f64,
+    pub bytes: u64,
+}
+
+impl FileSize {
+    /// Display the size in the largest unit that keeps it above 1
+    pub fn from_bytes(bytes: u64) -> Self {
+        let unit = SizeUnit::UNITS
+            .into_iter()
+            .rev()
+            .find(|unit| bytes >= unit.num_bytes())
+            .unwrap_or(SizeUnit::Bytes);
+
+        FileSize {
+            unit,
+            size: bytes as f64 / unit.num_bytes() as f64,
+            bytes,
+        }
+    }
+}
+
+impl FromStr for FileSize {
+    type Err = Error;
+
+    /// Parse sizes in the format `1.5 GiB`, optionally wrapped in parentheses
+    fn from_str(s: &str) -> Result<Self> {
+        let (size, unit) = s
+            .trim()
+            .trim_start_matches('(')
+            .trim_end_matches(')')
+            .split_once(' ')
+            .context(format!("Failed to parse size: {}", s))?;
+
+        let size: f64 = size
+            .trim()
+            .parse()
+            .context(format!("Invalid size: {}", size))?;
+        let unit = SizeUnit::from_str(unit.trim())?;
+
+        Ok(FileSize {
+            unit,
+            size,
+            bytes: (size * unit.num_bytes() as f64).round() as u64,
+        })
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub enum Category {
+    All,
+    Anime,
+    Audio,
     Manga,
+    LiveAction,
+    Pictures,
+    Software,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum ReleaseStatus {
+    Normal,
+    Trusted,
+    Remake,
+    Batch,
+}
+
+impl ReleaseStatus {
+    /// Nyaa has no batch marker of its own, so releases without a status from the site
+    /// are batches when their title covers a range of volumes or chapters
+    pub fn or_batch(self, title: &str) -> Self {
+        match self {
+            ReleaseStatus::Normal if ParsedRelease::parse(title).is_batch() => ReleaseStatus::Batch,
+            status => status,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Clone)]
 pub struct MediaInfo {
     pub id: String,
     pub category: Category,
@@ -39,6 +180,42 @@ pub struct MediaInfo {
     pub seeders: u32,
     pub leechers: u32,
     pub completed: u32,
+    pub info_hash: String,
+    pub status: ReleaseStatus,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type")]
+pub enum FileTreeNode {
+    Folder {
+        name: String,
+        children: Vec<FileTreeNode>,
+    },
+    File {
+        name: String,
+        size: Option<FileSize>,
+    },
+}
+
+#[derive(Debug, Serialize)]
+pub struct Comment {
+    pub author: String,
+    pub timestamp: DateTime<Utc>,
+    /// Comment body in markdown
+    pub content: String,
+}
+
+/// Everything listed on the detail page of a release
+#[derive(Debug, Serialize)]
+pub struct TorrentDetails {
+    pub info: MediaInfo,
+    /// `None` for anonymous uploads
+    pub submitter: Option<String>,
+    pub website: Option<String>,
+    /// Description in markdown
+    pub description: String,
+    pub files: Vec<FileTreeNode>,
+    pub comments: Vec<Comment>,
 }
 
 #[derive(Debug, Serialize, PartialEq)]
@@ -49,15 +226,138 @@ pub struct PaginationInfo {
     pub has_next: bool,
 }
 
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub enum SortField {
+    Date,
+    Size,
+    Comments,
+    Seeders,
+    Leechers,
+    Downloads,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub enum SortOrder {
+    Ascending,
+    Descending,
+}
+
+/// Source agnostic search query.
+/// Each source is responsible for translating this into its own request url.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct SearchQuery {
+    pub text: String,
+    pub category: Option<NyaaCategory>,
+    pub filter: Option<NyaaFilter>,
+    pub sort: Option<SortField>,
+    pub order: Option<SortOrder>,
+    pub page: Option<u32>,
+    pub uploader: Option<String>,
+    /// Only search this source, or every registered source when `None`
+    pub source: Option<Sources>,
+    /// Skip any cached results and fetch them again
+    #[serde(skip)]
+    pub refresh: bool,
+}
+
 #[async_trait]
 pub trait Source: Send + Sync {
     fn normalize_title(&self, title: &str) -> String;
 
-    async fn search(&self, query: &str) -> Result<(Vec<MediaInfo>, PaginationInfo)>;
+    async fn search(&self, query: &SearchQuery) -> Result<(Vec<MediaInfo>, PaginationInfo)>;
+
+    /// Download the release with `id` into a folder under `file_path`, returning that folder.
+    /// Only the files at the `only_files` indices are downloaded when it is set.
+    async fn download(
+        &self,
+        id: &str,
+        file_path: &Path,
+        only_files: Option<Vec<usize>>,
+    ) -> Result<PathBuf>;
 
-    async fn download(&self, id: &str, file_path: &Path) -> Result<PathBuf>;
+    /// Files in the release with `id`, before downloading it
+    async fn list_files(&self, id: &str) -> Result<Vec<TorrentFile>>;
 
     async fn get_info_by_id(&self, id: &str) -> Result<MediaInfo>;
 
+    async fn get_details_by_id(&self, id: &str) -> Result<TorrentDetails>;
+
+    /// Id of the release `url` links to, if it is one of this source's pages
+    fn id_from_url(&self, url: &str) -> Option<String>;
+
     fn get_variant(&self) -> Sources;
 }
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    #[rstest]
+    #[case(
+        ReleaseStatus::Normal,
+        "Oshi no Ko v01 (2020) (Digital)",
+        ReleaseStatus::Normal
+    )]
+    #[case(
+        ReleaseStatus::Normal,
+        "Oshi no Ko v01-16 (2020-2025) (Digital)",
+        ReleaseStatus::Batch
+    )]
+    #[case(
+        ReleaseStatus::Trusted,
+        "Oshi no Ko v01-16 (2020-2025) (Digital)",
+        ReleaseStatus::Trusted
+    )]
+    #[case(
+        ReleaseStatus::Remake,
+        "Oshi no Ko 001-166 (2022-2024)",
+        ReleaseStatus::Remake
+    )]
+    fn test_or_batch(
+        #[case] status: ReleaseStatus,
+        #[case] title: &str,
+        #[case] expected: ReleaseStatus,
+    ) {
+        assert_eq!(status.or_batch(title), expected);
+    }
+
+    #[rstest]
+    #[case("512 Bytes", SizeUnit::Bytes, 512.0, 512)]
+    #[case("1 KiB", SizeUnit::KiB, 1.0, 1024)]
+    #[case("241.3 MiB", SizeUnit::MiB, 241.3, 253021389)]
+    #[case("2 GiB", SizeUnit::GiB, 2.0, 2147483648)]
+    #[case("1.5 TiB", SizeUnit::TiB, 1.5, 1649267441664)]
+    #[case("(12.0 KiB)", SizeUnit::KiB, 12.0, 12288)]
+    fn test_parse_file_size(
+        #[case] s: &str,
+        #[case] unit: SizeUnit,
+        #[case] size: f64,
+        #[case] bytes: u64,
+    ) {
+        assert_eq!(
+            FileSize::from_str(s).unwrap(),
+            FileSize { unit, size, bytes }
+        );
+    }
+
+    #[rstest]
+    #[case("")]
+    #[case("12")]
+    #[case("12 PiB")]
+    #[case("big GiB")]
+    fn test_parse_invalid_file_size(#[case] s: &str) {
+        assert!(FileSize::from_str(s).is_err());
+    }
+
+    #[rstest]
+    #[case(0, SizeUnit::Bytes, 0.0)]
+    #[case(1023, SizeUnit::Bytes, 1023.0)]
+    #[case(1536, SizeUnit::KiB, 1.5)]
+    #[case(3 << 30, SizeUnit::GiB, 3.0)]
+    fn test_file_size_from_bytes(#[case] bytes: u64, #[case] unit: SizeUnit, #[case] size: f64) {
+        assert_eq!(FileSize::from_bytes(bytes), FileSize { unit, size, bytes });
+    }
+}
@@ -0,0 +1,134 @@
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+struct CacheEntry {
+    content: String,
+    fetched_at: Instant,
+    /// Insertion order, used to evict the oldest entry when the cache is full
+    sequence: u64,
+}
+
+#[derive(Default)]
+struct CacheState {
+    entries: HashMap<String, CacheEntry>,
+    next_sequence: u64,
+}
+
+/// Responses fetched by sources, keyed by url, so repeated lookups of the same page
+/// within `ttl` don't hit the site again
+pub struct ResponseCache {
+    state: Mutex<CacheState>,
+    ttl: Duration,
+    max_entries: usize,
+}
+
+impl ResponseCache {
+    pub fn new(ttl: Duration, max_entries: usize) -> Self {
+        ResponseCache {
+            state: Mutex::new(CacheState::default()),
+            ttl,
+            max_entries,
+        }
+    }
+
+    pub fn get(&self, url: &str) -> Option<String> {
+        self.get_within(url, self.ttl)
+    }
+
+    /// Like `get`, but only for responses fetched less than `max_age` ago.
+    /// `max_age` can only shorten the cache's ttl.
+    pub fn get_within(&self, url: &str, max_age: Duration) -> Option<String> {
+        let max_age = max_age.min(self.ttl);
+        let state = self.state.lock().unwrap();
+        state
+            .entries
+            .get(url)
+            .filter(|entry| entry.fetched_at.elapsed() < max_age)
+            .map(|entry| entry.content.clone())
+    }
+
+    pub fn insert(&self, url: &str, content: String) {
+        if self.max_entries == 0 {
+            return;
+        }
+
+        let mut state = self.state.lock().unwrap();
+        let entries = &mut state.entries;
+        if !entries.contains_key(url) && entries.len() >= self.max_entries {
+            entries.retain(|_, entry| entry.fetched_at.elapsed() < self.ttl);
+
+            if entries.len() >= self.max_entries {
+                let oldest = entries
+                    .iter()
+                    .min_by_key(|(_, entry)| entry.sequence)
+                    .map(|(url, _)| url.clone());
+                if let Some(oldest) = oldest {
+                    log::trace!("Evicting {} from response cache", oldest);
+                    entries.remove(&oldest);
+                }
+            }
+        }
+
+        let sequence = state.next_sequence;
+        state.next_sequence += 1;
+        state.entries.insert(
+            url.to_owned(),
+            CacheEntry {
+                content,
+                fetched_at: Instant::now(),
+                sequence,
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cache_hit() {
+        let cache = ResponseCache::new(Duration::from_secs(60), 10);
+        assert_eq!(cache.get("https://nyaa.si/view/1"), None);
+
+        cache.insert("https://nyaa.si/view/1", "page".to_owned());
+        assert_eq!(cache.get("https://nyaa.si/view/1"), Some("page".to_owned()));
+    }
+
+    #[test]
+    fn test_cache_max_age() {
+        let cache = ResponseCache::new(Duration::from_secs(60), 10);
+        cache.insert("https://nyaa.si/view/1", "page".to_owned());
+
+        assert_eq!(
+            cache.get_within("https://nyaa.si/view/1", Duration::from_secs(30)),
+            Some("page".to_owned())
+        );
+        assert_eq!(
+            cache.get_within("https://nyaa.si/view/1", Duration::ZERO),
+            None
+        );
+    }
+
+    #[test]
+    fn test_cache_expiry() {
+        let cache = ResponseCache::new(Duration::ZERO, 10);
+        cache.insert("https://nyaa.si/view/1", "page".to_owned());
+        assert_eq!(cache.get("https://nyaa.si/view/1"), None);
+    }
+
+    #[test]
+    fn test_cache_evicts_oldest() {
+        let cache = ResponseCache::new(Duration::from_secs(60), 2);
+        cache.insert("1", "first".to_owned());
+        cache.insert("2", "second".to_owned());
+        cache.insert("3", "third".to_owned());
+
+        assert_eq!(cache.get("1"), None);
+        assert_eq!(cache.get("2"), Some("second".to_owned()));
+        assert_eq!(cache.get("3"), Some("third".to_owned()));
+    }
+}
@@ -1,47 +1,82 @@
 use crate::{
-    source::{nyaa::category::NyaaCategory, MediaInfo, PaginationInfo, Sources},
-    torrent::TorrentService,
+    http::HttpClient,
+    source::{
+        cache::ResponseCache,
+        nyaa::{category::NyaaCategory, query_params::QueryParam},
+        MediaInfo, PaginationInfo, ReleaseStatus, SearchQuery, Sources, TorrentDetails,
+    },
+    torrent::{TorrentFile, TorrentService, TorrentSource},
 };
 
 use super::{FileSize, Source};
-use anyhow::{anyhow, Context, Result};
+use anyhow::{bail, Context, Result};
 use async_trait::async_trait;
 use chrono::DateTime;
 use regex::Regex;
 use scraper::{CaseSensitivity, ElementRef, Html, Selector};
+use serde::{Deserialize, Serialize};
 use std::{
     path::{Path, PathBuf},
-    sync::Arc,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
 };
 use tokio::sync::Mutex;
 use url::Url;
 
+/// Number of results nyaa returns per page
+const NYAA_PAGE_SIZE: usize = 75;
+
+pub const NYAA_URL: &str = "https://nyaa.si/";
+
+pub const DEFAULT_VIEW_TTL: Duration = Duration::from_secs(30);
+
+/// Trackers that nyaa includes in its own magnet links
+const NYAA_TRACKERS: [&str; 5] = [
+    "http://nyaa.tracker.wf:7777/announce",
+    "udp://open.stealth.si:80/announce",
+    "udp://tracker.opentrackr.org:1337/announce",
+    "udp://exodus.desync.com:6969/announce",
+    "udp://tracker.torrent.eu.org:451/announce",
+];
+
+/// How search results are fetched from nyaa
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum NyaaBackend {
+    /// Scrape the html listing, falling back to the rss feed if that fails
+    #[default]
+    Html,
+    /// Only use the rss feed
+    Rss,
+}
+
 pub struct Nyaa {
-    base_url: Url,
-    client: reqwest::Client,
+    /// The base url followed by any fallback mirrors, in the order they are tried
+    mirrors: Vec<Url>,
+    /// Index of the last mirror that responded successfully
+    active_mirror: AtomicUsize,
+    client: HttpClient,
     torrent_service: Arc<Mutex<dyn TorrentService>>,
+    backend: NyaaBackend,
+    cache: Option<Arc<ResponseCache>>,
+    /// View pages show live seeder counts, so they are reused for less time than listings
+    view_ttl: Duration,
 }
 
-mod category;
+pub mod category;
 pub mod query_params;
-
-impl FileSize {
-    fn from(s: &str, re: &Regex) -> Result<Self> {
-        let caps = re.captures(s).context("Failed to parse size")?;
-        let size = caps["size"].parse()?;
-        match &caps["unit"] {
-            "MiB" => Ok(FileSize::MiB(size)),
-            "GiB" => Ok(FileSize::GiB(size)),
-            unit => Err(anyhow!("Unrecognized file size unit {}", unit)),
-        }
-    }
-}
+mod rss;
+mod view;
 
 struct NyaaParseConfig {
     category: Selector,
     title: Selector,
+    magnet: Selector,
+    info_hash_regex: Regex,
     size: Selector,
-    size_regex: Regex,
     timestamp: Selector,
     seeders: Selector,
     leechers: Selector,
@@ -53,8 +88,9 @@ impl NyaaParseConfig {
         Self {
             category: Selector::parse("td:nth-child(1) a").unwrap(),
             title: Selector::parse("td:nth-child(2) a:last-child").unwrap(),
+            magnet: Selector::parse("td:nth-child(3) a[href^='magnet:']").unwrap(),
+            info_hash_regex: Regex::new(r"urn:btih:(?<hash>[0-9a-fA-F]{40})").unwrap(),
             size: Selector::parse("td:nth-child(4)").unwrap(),
-            size_regex: Regex::new(r"(?<size>[0-9]+\.[0-9]+) (?<unit>MiB|GiB)").unwrap(),
             timestamp: Selector::parse("td:nth-child(5)").unwrap(),
             seeders: Selector::parse("td:nth-child(6)").unwrap(),
             leechers: Selector::parse("td:nth-child(7)").unwrap(),
@@ -64,21 +100,254 @@ impl NyaaParseConfig {
 }
 
 impl Nyaa {
-    pub fn new(torrent_service: Arc<Mutex<dyn TorrentService>>, client: reqwest::Client) -> Self {
+    pub fn new(torrent_service: Arc<Mutex<dyn TorrentService>>, client: HttpClient) -> Self {
         Self {
-            base_url: Url::parse("https://nyaa.si/").unwrap(),
+            mirrors: vec![Url::parse(NYAA_URL).unwrap()],
+            active_mirror: AtomicUsize::new(0),
             client,
             torrent_service,
+            backend: NyaaBackend::Html,
+            cache: None,
+            view_ttl: DEFAULT_VIEW_TTL,
+        }
+    }
+
+    pub fn with_backend(mut self, backend: NyaaBackend) -> Self {
+        self.backend = backend;
+        self
+    }
+
+    /// Cache responses, which can be shared with other sources
+    pub fn with_cache(mut self, cache: Arc<ResponseCache>) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// How long cached view pages are reused for, capped by the cache's own ttl
+    pub fn with_view_ttl(mut self, view_ttl: Duration) -> Self {
+        self.view_ttl = view_ttl;
+        self
+    }
+
+    /// Replace the primary url used to reach nyaa, e.g. for a local proxy
+    pub fn with_base_url(mut self, base_url: Url) -> Self {
+        self.mirrors[0] = Nyaa::normalize_base_url(base_url);
+        self
+    }
+
+    /// Mirrors to fall back through, in order, when the base url fails
+    pub fn with_mirrors(mut self, mirrors: Vec<Url>) -> Self {
+        self.mirrors.truncate(1);
+        self.mirrors
+            .extend(mirrors.into_iter().map(Nyaa::normalize_base_url));
+        self
+    }
+
+    /// The base url needs a trailing slash, otherwise joining drops the last path segment
+    fn normalize_base_url(mut url: Url) -> Url {
+        if !url.path().ends_with('/') {
+            url.set_path(&format!("{}/", url.path()));
         }
+        url
     }
 
-    async fn fetch_page(&self, url: &Url) -> Result<Html> {
-        let request = self.client.get(url.as_str());
-        let response = request.send().await?;
-        let content = response.text().await?;
+    fn base_url(&self) -> &Url {
+        &self.mirrors[self.active_mirror.load(Ordering::Relaxed)]
+    }
+
+    /// Fetch the page built by `build_url`, using the cached response when there is one
+    /// younger than `max_age`, or the cache's ttl when `None`.
+    /// Responses are cached under their url on the primary mirror.
+    async fn fetch(
+        &self,
+        build_url: impl Fn(&Url) -> Result<Url>,
+        max_age: Option<Duration>,
+    ) -> Result<String> {
+        let Some(cache) = &self.cache else {
+            return self.fetch_from_mirrors(build_url).await;
+        };
+
+        let cache_key = build_url(&self.mirrors[0])?.to_string();
+        let cached = match max_age {
+            Some(max_age) => cache.get_within(&cache_key, max_age),
+            None => cache.get(&cache_key),
+        };
+        if let Some(content) = cached {
+            log::debug!("Using cached response for {}", cache_key);
+            return Ok(content);
+        }
+
+        let content = self.fetch_from_mirrors(build_url).await?;
+        cache.insert(&cache_key, content.clone());
+        Ok(content)
+    }
+
+    /// Fetch from the active mirror, falling back through the rest on failure.
+    /// `build_url` creates the request url relative to a mirror's base url.
+    async fn fetch_from_mirrors(&self, build_url: impl Fn(&Url) -> Result<Url>) -> Result<String> {
+        let active = self.active_mirror.load(Ordering::Relaxed);
+        let mut last_err = None;
+
+        for offset in 0..self.mirrors.len() {
+            let index = (active + offset) % self.mirrors.len();
+            let url = build_url(&self.mirrors[index])?;
+
+            log::debug!("Fetching {}", url);
+            match self.fetch_from(&url).await {
+                Ok(content) => {
+                    if index != active {
+                        log::info!("Switching to mirror {}", self.mirrors[index]);
+                        self.active_mirror.store(index, Ordering::Relaxed);
+                    }
+                    return Ok(content);
+                }
+                Err(err) => {
+                    log::warn!("Request to {} failed: {}", url, err);
+                    last_err = Some(err);
+                }
+            }
+        }
+
+        Err(last_err.context("No mirrors configured for nyaa")?)
+    }
+
+    async fn fetch_from(&self, url: &Url) -> Result<String> {
+        let response = self.client.get(url).await?;
+        Ok(response.text().await?)
+    }
+
+    async fn fetch_page(
+        &self,
+        build_url: impl Fn(&Url) -> Result<Url>,
+        max_age: Option<Duration>,
+    ) -> Result<Html> {
+        let content = self.fetch(build_url, max_age).await?;
         Ok(Html::parse_document(&content))
     }
 
+    async fn search_html(&self, query: &SearchQuery) -> Result<(Vec<MediaInfo>, PaginationInfo)> {
+        log::info!("Searching for {:?}", query);
+
+        let html = self
+            .fetch_page(
+                |base_url| Ok(Nyaa::get_listing_url(base_url, query)),
+                query.refresh.then_some(Duration::ZERO),
+            )
+            .await?;
+
+        let selector = Selector::parse("tbody tr").unwrap();
+        let rows: Vec<ElementRef> = html.select(&selector).collect();
+
+        let config = NyaaParseConfig::new();
+
+        log::debug!("Parsing Nyaa table rows");
+
+        let media_info: Vec<MediaInfo> = rows
+            .iter()
+            .filter_map(|row| {
+                Nyaa::parse_row(*row, &config)
+                    .map_err(|err| {
+                        log::warn!("{}", err);
+                        err
+                    })
+                    .ok()
+            })
+            .collect();
+
+        // an empty listing is a valid answer, rows that can't be read mean the layout changed
+        if media_info.is_empty() && !rows.is_empty() {
+            bail!("None of the {} result rows could be parsed", rows.len());
+        }
+
+        Ok((media_info, Nyaa::get_pagination_info(&html)?))
+    }
+
+    async fn search_rss(&self, query: &SearchQuery) -> Result<(Vec<MediaInfo>, PaginationInfo)> {
+        log::info!("Searching rss for {:?}", query);
+
+        let content = self
+            .fetch(
+                |base_url| {
+                    let mut url = Nyaa::get_search_url(base_url, query);
+                    url.query_pairs_mut().append_pair("page", "rss");
+                    Ok(url)
+                },
+                query.refresh.then_some(Duration::ZERO),
+            )
+            .await?;
+        let media_info = rss::parse_feed(&content)?;
+        let pagination = rss::get_pagination_info(query.page.unwrap_or(1), media_info.len());
+
+        Ok((media_info, pagination))
+    }
+
+    fn get_search_url(base_url: &Url, query: &SearchQuery) -> Url {
+        let mut url = base_url.clone();
+        {
+            let mut params = url.query_pairs_mut();
+            if let Some(filter) = &query.filter {
+                params.append_pair("f", &filter.to_query_param());
+            }
+            if let Some(category) = &query.category {
+                params.append_pair("c", &category.to_query_param());
+            }
+            params.append_pair("q", query.text.trim());
+            if let Some(uploader) = &query.uploader {
+                params.append_pair("u", uploader);
+            }
+            if let Some(sort) = &query.sort {
+                params.append_pair("s", &sort.to_query_param());
+            }
+            if let Some(order) = &query.order {
+                params.append_pair("o", &order.to_query_param());
+            }
+            if let Some(page) = query.page {
+                params.append_pair("p", &page.to_string());
+            }
+        }
+        url
+    }
+
+    /// Url of the html listing for `query`. Uploads by a single user are listed on
+    /// `/user/{name}`, which takes the same params and paginates the same way as search.
+    fn get_listing_url(base_url: &Url, query: &SearchQuery) -> Url {
+        let Some(uploader) = &query.uploader else {
+            return Nyaa::get_search_url(base_url, query);
+        };
+
+        let mut url = Nyaa::get_search_url(
+            base_url,
+            &SearchQuery {
+                uploader: None,
+                ..query.clone()
+            },
+        );
+        if let Ok(mut segments) = url.path_segments_mut() {
+            segments.pop_if_empty().push("user").push(uploader);
+        }
+        url
+    }
+
+    fn get_torrent_file_url(&self, id: &str) -> Result<Url> {
+        Ok(self
+            .base_url()
+            .join("download/")?
+            .join(&format!("{}.torrent", id))?)
+    }
+
+    fn get_magnet_uri(info_hash: &str, title: &str) -> String {
+        let mut uri = format!(
+            "magnet:?xt=urn:btih:{}&dn={}",
+            info_hash,
+            url::form_urlencoded::byte_serialize(title.as_bytes()).collect::<String>()
+        );
+        for tracker in NYAA_TRACKERS {
+            uri.push_str("&tr=");
+            uri.extend(url::form_urlencoded::byte_serialize(tracker.as_bytes()));
+        }
+        uri
+    }
+
     fn extract_id_from_href(href: &str) -> Result<String> {
         href.split("/")
             .last()
@@ -86,6 +355,26 @@ impl Nyaa {
             .context(format!("Missing id on href: {}", href))
     }
 
+    /// Nyaa highlights trusted uploads with `success` and remakes with `danger`.
+    /// The view page uses the same classes with a `panel-` prefix.
+    fn get_release_status(element: &ElementRef, class_prefix: &str) -> ReleaseStatus {
+        let has_class = |class: &str| {
+            element.value().has_class(
+                &format!("{}{}", class_prefix, class),
+                CaseSensitivity::AsciiCaseInsensitive,
+            )
+        };
+
+        // remakes take precedence over trusted, same as on the site
+        if has_class("danger") {
+            ReleaseStatus::Remake
+        } else if has_class("success") {
+            ReleaseStatus::Trusted
+        } else {
+            ReleaseStatus::Normal
+        }
+    }
+
     fn parse_row(row: ElementRef, config: &NyaaParseConfig) -> Result<MediaInfo> {
         let category = row
             .select(&config.category)
@@ -109,6 +398,19 @@ impl Nyaa {
             .context("Missing id for row")
             .and_then(Nyaa::extract_id_from_href)?;
 
+        let magnet = row
+            .select(&config.magnet)
+            .next()
+            .context("Missing magnet link for row")?
+            .attr("href")
+            .context("Magnet link missing 'href' element")?;
+
+        let info_hash = config
+            .info_hash_regex
+            .captures(magnet)
+            .context(format!("Missing info hash in magnet link: {}", magnet))?["hash"]
+            .to_lowercase();
+
         let size = row
             .select(&config.size)
             .next()
@@ -148,15 +450,19 @@ impl Nyaa {
             .collect::<String>()
             .parse()?;
 
+        let status = Nyaa::get_release_status(&row, "").or_batch(&title);
+
         Ok(MediaInfo {
             id,
             category: NyaaCategory::from_query_param(category)?.to_source_category(),
             title,
-            size: FileSize::from(&size, &config.size_regex)?,
+            size: FileSize::from_str(&size)?,
             timestamp: DateTime::from_timestamp(timestamp, 0).context("Invalid timestamp")?,
             seeders,
             leechers,
             completed,
+            info_hash,
+            status,
         })
     }
 
@@ -227,110 +533,77 @@ impl Source for Nyaa {
         multi_space.replace_all(&normalized, " ").to_string()
     }
 
-    async fn search(&self, query: &str) -> Result<(Vec<MediaInfo>, PaginationInfo)> {
-        log::info!("Searching for {}", query);
-        let mut url = self.base_url.clone();
-        url.set_query(Some(query));
-
-        let html = self.fetch_page(&url).await?;
-
-        let selector = Selector::parse("tr").unwrap();
-        let rows = html.select(&selector);
-
-        let config = NyaaParseConfig::new();
-
-        log::debug!("Parsing Nyaa table rows");
-
-        let media_info = rows
-            .filter_map(|row| {
-                Nyaa::parse_row(row, &config)
-                    .map_err(|err| {
-                        log::warn!("{}", err);
-                        err
-                    })
-                    .ok()
-            })
-            .collect();
-
-        Ok((media_info, Nyaa::get_pagination_info(&html)?))
+    async fn search(&self, query: &SearchQuery) -> Result<(Vec<MediaInfo>, PaginationInfo)> {
+        match self.backend {
+            NyaaBackend::Rss => self.search_rss(query).await,
+            NyaaBackend::Html => match self.search_html(query).await {
+                Ok(results) => Ok(results),
+                Err(err) => {
+                    log::warn!("Failed to scrape html ({}), falling back to rss", err);
+                    self.search_rss(query).await
+                }
+            },
+        }
     }
 
-    async fn download(&self, id: &str, base_dir: &Path) -> Result<PathBuf> {
-        log::info!("Starting download for {}view/{}", self.base_url, id);
+    async fn download(
+        &self,
+        id: &str,
+        base_dir: &Path,
+        only_files: Option<Vec<usize>>,
+    ) -> Result<PathBuf> {
+        log::info!("Starting download for {}view/{}", self.base_url(), id);
 
-        let url = self
-            .base_url
-            .join("download/")?
-            .join(&format!("{}.torrent", id))?;
-
-        let title = self.get_info_by_id(id).await?.title;
-        let output_dir = base_dir.join(&title);
+        let info = self.get_info_by_id(id).await?;
+        let output_dir = base_dir.join(&info.title);
 
+        let source = TorrentSource::MagnetWithFallback {
+            magnet: Nyaa::get_magnet_uri(&info.info_hash, &info.title),
+            torrent_file: self.get_torrent_file_url(id)?,
+        };
+        // resolving a magnet can take up to its timeout, the service is only locked to add it
+        let resolve = self
+            .torrent_service
+            .lock()
+            .await
+            .resolve_torrent(id, &source);
+        let torrent = resolve.await?;
         self.torrent_service
             .lock()
             .await
-            .download_torrent(id, &url, &format!("{}.torrent", title), &output_dir)
+            .download_torrent(id, &torrent, &output_dir, only_files)
             .await?;
 
         Ok(output_dir)
     }
 
-    async fn get_info_by_id(&self, id: &str) -> Result<MediaInfo> {
-        let url = self.base_url.join("view/")?.join(id)?;
-        let html = self.fetch_page(&url).await?;
-        let title_selector = Selector::parse(".panel-title").expect("title selector to be valid");
+    async fn list_files(&self, id: &str) -> Result<Vec<TorrentFile>> {
+        // the .torrent file lists the files without having to find peers first
+        let source = TorrentSource::TorrentFile(self.get_torrent_file_url(id)?);
+        self.torrent_service.lock().await.list_files(&source).await
+    }
 
-        let title = html
-            .select(&title_selector)
-            .next()
-            .context(format!("Missing title for id: {}", id))?
-            .text()
-            .collect::<String>()
-            .trim()
-            .to_owned();
+    async fn get_info_by_id(&self, id: &str) -> Result<MediaInfo> {
+        Ok(self.get_details_by_id(id).await?.info)
+    }
 
-        let timestamp = html
-            .select(
-                &Selector::parse("div.row:nth-child(1) > div:nth-child(4)")
-                    .expect("valid timestamp selector"),
+    async fn get_details_by_id(&self, id: &str) -> Result<TorrentDetails> {
+        let html = self
+            .fetch_page(
+                |base_url| Ok(base_url.join("view/")?.join(id)?),
+                Some(self.view_ttl),
             )
-            .next()
-            .context("Missing timestamp")?
-            .attr("data-timestamp")
-            .context("Missing timestamp")?
-            .parse()
-            .context("Invalid timestamp")?;
-
-        let cols = html
-            .select(&Selector::parse(".panel-body .col-md-5").expect("invalid body selector"))
-            .map(|element| element.text().collect())
-            .collect::<Vec<String>>();
-
-        let config = NyaaParseConfig::new();
+            .await?;
+        view::parse_view_page(id, &html)
+    }
 
-        Ok(MediaInfo {
-            id: id.to_owned(),
-            category: NyaaCategory::from_str(cols.get(0).expect("Missing category"))
-                .to_source_category(),
-            title,
-            size: FileSize::from(cols.get(6).expect("Missing file size"), &config.size_regex)?,
-            timestamp: DateTime::from_timestamp(timestamp, 0).context("Invalid timestamp")?,
-            seeders: cols
-                .get(3)
-                .expect("Missing seederes")
-                .parse()
-                .context("failed to parse seeders")?,
-            leechers: cols
-                .get(5)
-                .expect("Missing seederes")
-                .parse()
-                .context("failed to parse leechers")?,
-            completed: cols
-                .get(7)
-                .expect("Missing seederes")
-                .parse()
-                .context("failed to parse completed")?,
-        })
+    fn id_from_url(&self, url: &str) -> Option<String> {
+        // torrent files link to nyaa itself, whichever mirror they came from
+        let base_urls = std::iter::once(NYAA_URL).chain(self.mirrors.iter().map(Url::as_str));
+        base_urls
+            .filter_map(|base_url| url.strip_prefix(base_url)?.strip_prefix("view/"))
+            .find(|id| !id.is_empty() && id.chars().all(|c| c.is_ascii_digit()))
+            .map(str::to_owned)
     }
 
     fn get_variant(&self) -> Sources {
@@ -341,11 +614,31 @@ impl Source for Nyaa {
 #[cfg(test)]
 mod tests {
     use rstest::rstest;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
 
-    use crate::torrent::rqbit_service::MockRqbitService;
+    use crate::{
+        http::HttpSettings,
+        source::{
+            nyaa::{category::LiteratureSubCategory, query_params::NyaaFilter},
+            SortField, SortOrder,
+        },
+        torrent::rqbit_service::MockRqbitService,
+    };
 
     use super::*;
 
+    fn nyaa() -> Nyaa {
+        Nyaa::new(
+            Arc::new(Mutex::new(MockRqbitService::new())),
+            // no throttling or retries so failures show up straight away
+            HttpClient::new(reqwest::Client::new()).with_settings(HttpSettings {
+                requests_per_second: 0.0,
+                max_retries: 0,
+                ..Default::default()
+            }),
+        )
+    }
+
     #[rstest]
     #[case(
         "【OSHI NO KO】 001-166 (2022-2024) (Digital) (Antrill) [Completed]",
@@ -366,11 +659,140 @@ mod tests {
         "i'm the evil lord of an intergalactic empire!"
     )]
     fn test_normalize_title(#[case] title: &str, #[case] expected: &str) {
-        let nyaa = Nyaa::new(
-            Arc::new(Mutex::new(MockRqbitService::new())),
-            reqwest::Client::new(),
-        );
-        let actual = nyaa.normalize_title(title);
+        let actual = nyaa().normalize_title(title);
         assert_eq!(actual, expected);
     }
+
+    #[rstest]
+    #[case(SearchQuery::default(), "https://nyaa.si/?q=")]
+    #[case(
+        SearchQuery {
+            text: "oshi no ko".to_owned(),
+            category: Some(NyaaCategory::Literature(LiteratureSubCategory::EnglishTranslated)),
+            filter: Some(NyaaFilter::TrustedOnly),
+            page: Some(2),
+            ..Default::default()
+        },
+        "https://nyaa.si/?f=2&c=3_1&q=oshi+no+ko&p=2"
+    )]
+    #[case(
+        SearchQuery {
+            uploader: Some("Oak".to_owned()),
+            sort: Some(SortField::Seeders),
+            order: Some(SortOrder::Descending),
+            ..Default::default()
+        },
+        "https://nyaa.si/?q=&u=Oak&s=seeders&o=desc"
+    )]
+    fn test_search_url(#[case] query: SearchQuery, #[case] expected: &str) {
+        let base_url = Url::parse(NYAA_URL).unwrap();
+        assert_eq!(Nyaa::get_search_url(&base_url, &query).as_str(), expected);
+    }
+
+    #[rstest]
+    #[case(SearchQuery::default(), "https://nyaa.si/?q=")]
+    #[case(
+        SearchQuery {
+            text: "oshi no ko".to_owned(),
+            uploader: Some("Oak".to_owned()),
+            page: Some(2),
+            ..Default::default()
+        },
+        "https://nyaa.si/user/Oak?q=oshi+no+ko&p=2"
+    )]
+    #[case(
+        SearchQuery {
+            uploader: Some("1r0n & co".to_owned()),
+            ..Default::default()
+        },
+        "https://nyaa.si/user/1r0n%20&%20co?q="
+    )]
+    fn test_listing_url(#[case] query: SearchQuery, #[case] expected: &str) {
+        let base_url = Url::parse(NYAA_URL).unwrap();
+        assert_eq!(Nyaa::get_listing_url(&base_url, &query).as_str(), expected);
+    }
+
+    #[test]
+    fn test_magnet_uri() {
+        assert_eq!(
+            Nyaa::get_magnet_uri("0123456789abcdef0123456789abcdef01234567", "Oshi no Ko v01"),
+            "magnet:?xt=urn:btih:0123456789abcdef0123456789abcdef01234567&dn=Oshi+no+Ko+v01\
+            &tr=http%3A%2F%2Fnyaa.tracker.wf%3A7777%2Fannounce\
+            &tr=udp%3A%2F%2Fopen.stealth.si%3A80%2Fannounce\
+            &tr=udp%3A%2F%2Ftracker.opentrackr.org%3A1337%2Fannounce\
+            &tr=udp%3A%2F%2Fexodus.desync.com%3A6969%2Fannounce\
+            &tr=udp%3A%2F%2Ftracker.torrent.eu.org%3A451%2Fannounce"
+        );
+    }
+
+    #[rstest]
+    #[case("default", ReleaseStatus::Normal)]
+    #[case("success", ReleaseStatus::Trusted)]
+    #[case("danger", ReleaseStatus::Remake)]
+    fn test_release_status(#[case] class: &str, #[case] expected: ReleaseStatus) {
+        let html = Html::parse_fragment(&format!(
+            "<table><tbody><tr class=\"{}\"><td></td></tr></tbody></table>",
+            class
+        ));
+        let row = html.select(&Selector::parse("tr").unwrap()).next().unwrap();
+        assert_eq!(Nyaa::get_release_status(&row, ""), expected);
+    }
+
+    #[rstest]
+    #[case("http://localhost:8080", "http://localhost:8080/view/1")]
+    #[case("http://localhost:8080/nyaa", "http://localhost:8080/nyaa/view/1")]
+    #[case("http://localhost:8080/nyaa/", "http://localhost:8080/nyaa/view/1")]
+    fn test_base_url(#[case] base_url: &str, #[case] expected: &str) {
+        let nyaa = nyaa().with_base_url(Url::parse(base_url).unwrap());
+        assert_eq!(nyaa.base_url().join("view/1").unwrap().as_str(), expected);
+    }
+
+    #[rstest]
+    #[case("https://nyaa.si/view/1990813", Some("1990813"))]
+    #[case("http://localhost:8080/nyaa/view/1", Some("1"))]
+    #[case("https://nyaa.si/view/1990813/comments", None)]
+    #[case("https://nyaa.si/download/1990813.torrent", None)]
+    #[case("https://example.com/view/1990813", None)]
+    #[case("Ripped by Ushi", None)]
+    fn test_id_from_url(#[case] url: &str, #[case] expected: Option<&str>) {
+        let nyaa = nyaa().with_mirrors(vec![Url::parse("http://localhost:8080/nyaa").unwrap()]);
+        assert_eq!(nyaa.id_from_url(url).as_deref(), expected);
+    }
+
+    /// Serves a single http response with the given status and body
+    async fn serve_once(status: &str, body: &'static str) -> Url {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = Url::parse(&format!("http://{}/", listener.local_addr().unwrap())).unwrap();
+        let response = format!(
+            "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            status,
+            body.len(),
+            body
+        );
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buf = [0; 1024];
+            let _ = stream.read(&mut buf).await;
+            stream.write_all(response.as_bytes()).await.unwrap();
+        });
+        url
+    }
+
+    #[tokio::test]
+    async fn test_mirror_failover() {
+        let failing = serve_once("503 Service Unavailable", "down").await;
+        let working = serve_once("200 OK", "up").await;
+
+        let nyaa = nyaa()
+            .with_base_url(failing)
+            .with_mirrors(vec![working.clone()]);
+
+        let content = nyaa
+            .fetch(|base_url| Ok(base_url.clone()), None)
+            .await
+            .unwrap();
+        assert_eq!(content, "up");
+        // the working mirror is remembered for later requests
+        assert_eq!(nyaa.base_url(), &working);
+    }
 }
@@ -1,76 +1,242 @@
 use std::str::FromStr;
 
-use anyhow::{anyhow, Context, Ok, Result};
-use log::error;
+use anyhow::{anyhow, Context, Error, Result};
+use serde::{Deserialize, Serialize};
 use strum_macros::EnumString;
 
 use crate::source::Category;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
 pub enum NyaaCategory {
-    // AllCategories,
-    // Anime,
-    // Audio,
+    AllCategories,
+    Anime(AnimeSubCategory),
+    Audio(AudioSubCategory),
     Literature(LiteratureSubCategory),
-    // LiveAction,
-    // Pictures,
-    // Software,
+    LiveAction(LiveActionSubCategory),
+    Pictures(PicturesSubCategory),
+    Software(SoftwareSubCategory),
 }
 
-#[derive(Debug, EnumString)]
+// Each subcategory can be parsed from either its query param value or the
+// display name used on the view page, e.g. "1" or "English-translated".
+
+#[derive(Debug, Clone, Copy, PartialEq, EnumString)]
+pub enum AnimeSubCategory {
+    #[strum(serialize = "0")]
+    Base,
+
+    #[strum(serialize = "1", serialize = "Anime Music Video")]
+    MusicVideo,
+
+    #[strum(serialize = "2", serialize = "English-translated")]
+    EnglishTranslated,
+
+    #[strum(serialize = "3", serialize = "Non-English-translated")]
+    NonEnglishTranslated,
+
+    #[strum(serialize = "4", serialize = "Raw")]
+    Raw,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, EnumString)]
+pub enum AudioSubCategory {
+    #[strum(serialize = "0")]
+    Base,
+
+    #[strum(serialize = "1", serialize = "Lossless")]
+    Lossless,
+
+    #[strum(serialize = "2", serialize = "Lossy")]
+    Lossy,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, EnumString)]
 pub enum LiteratureSubCategory {
     #[strum(serialize = "0")]
     Base,
 
-    #[strum(serialize = "1")]
+    #[strum(serialize = "1", serialize = "English-translated")]
+    EnglishTranslated,
+
+    #[strum(serialize = "2", serialize = "Non-English-translated")]
+    NonEnglishTranslated,
+
+    #[strum(serialize = "3", serialize = "Raw")]
+    Raw,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, EnumString)]
+pub enum LiveActionSubCategory {
+    #[strum(serialize = "0")]
+    Base,
+
+    #[strum(serialize = "1", serialize = "English-translated")]
     EnglishTranslated,
 
-    #[strum(serialize = "2")]
+    #[strum(serialize = "2", serialize = "Idol/Promotional Video")]
+    IdolPromotionalVideo,
+
+    #[strum(serialize = "3", serialize = "Non-English-translated")]
     NonEnglishTranslated,
 
-    #[strum(serialize = "3")]
+    #[strum(serialize = "4", serialize = "Raw")]
     Raw,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, EnumString)]
+pub enum PicturesSubCategory {
+    #[strum(serialize = "0")]
+    Base,
+
+    #[strum(serialize = "1", serialize = "Graphics")]
+    Graphics,
+
+    #[strum(serialize = "2", serialize = "Photos")]
+    Photos,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, EnumString)]
+pub enum SoftwareSubCategory {
+    #[strum(serialize = "0")]
+    Base,
+
+    #[strum(serialize = "1", serialize = "Applications")]
+    Applications,
+
+    #[strum(serialize = "2", serialize = "Games")]
+    Games,
+}
+
+fn parse_subcategory<T: FromStr>(sub: &str) -> Result<T> {
+    T::from_str(sub)
+        .ok()
+        .context(format!("unhandled subcategory: {}", sub))
+}
+
 impl NyaaCategory {
     /// Parse category from the nyaa query param format
-    /// where `s` is in the format of `[major_category]_[subcategory]`
+    /// where `s` is in the format of `[major_category]_[subcategory]`,
+    /// optionally prefixed by `/?c=` as found in the listing hrefs
     pub fn from_query_param(s: &str) -> Result<Self> {
         let (main, sub) = s
             .strip_prefix("/?c=")
-            .context(format!("Invalid query string: {}", s))?
+            .unwrap_or(s)
             .split_once("_")
             .context(format!("Invalid category query param found: {}", s))?;
 
         match main {
-            "3" => Ok(NyaaCategory::Literature(
-                LiteratureSubCategory::from_str(sub)
-                    .context(format!("unhandled subcategory: {}", sub))?,
-            )),
+            "0" => Ok(NyaaCategory::AllCategories),
+            "1" => Ok(NyaaCategory::Anime(parse_subcategory(sub)?)),
+            "2" => Ok(NyaaCategory::Audio(parse_subcategory(sub)?)),
+            "3" => Ok(NyaaCategory::Literature(parse_subcategory(sub)?)),
+            "4" => Ok(NyaaCategory::LiveAction(parse_subcategory(sub)?)),
+            "5" => Ok(NyaaCategory::Pictures(parse_subcategory(sub)?)),
+            "6" => Ok(NyaaCategory::Software(parse_subcategory(sub)?)),
             _ => Err(anyhow!("unhandled main category: {}", main)),
         }
     }
 
     pub fn to_source_category(&self) -> Category {
         match self {
+            NyaaCategory::AllCategories => Category::All,
+            NyaaCategory::Anime(_) => Category::Anime,
+            NyaaCategory::Audio(_) => Category::Audio,
             NyaaCategory::Literature(_) => Category::Manga,
+            NyaaCategory::LiveAction(_) => Category::LiveAction,
+            NyaaCategory::Pictures(_) => Category::Pictures,
+            NyaaCategory::Software(_) => Category::Software,
         }
     }
+}
 
-    pub fn from_str(s: &str) -> Self {
-        match s.trim() {
-            "Literature - English-translated" => {
-                Self::Literature(LiteratureSubCategory::EnglishTranslated)
-            }
-
-            "Literature - Raw" => Self::Literature(LiteratureSubCategory::Raw),
-            "Literature - Non-English-translated" => {
-                Self::Literature(LiteratureSubCategory::NonEnglishTranslated)
-            }
-            _ => {
-                error!("Unsupported Category {}", s.trim());
-                unreachable!("Unsupported Category")
-            }
+impl FromStr for NyaaCategory {
+    type Err = Error;
+
+    /// Parse category from the display format used on the view page
+    /// e.g. `Literature - English-translated`
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.split_whitespace().collect::<Vec<&str>>().join(" ");
+        let (main, sub) = match s.split_once(" - ") {
+            Some((main, sub)) => (main, sub),
+            None => (s.as_str(), "0"),
+        };
+
+        match main {
+            "All categories" => Ok(NyaaCategory::AllCategories),
+            "Anime" => Ok(NyaaCategory::Anime(parse_subcategory(sub)?)),
+            "Audio" => Ok(NyaaCategory::Audio(parse_subcategory(sub)?)),
+            "Literature" => Ok(NyaaCategory::Literature(parse_subcategory(sub)?)),
+            "Live Action" => Ok(NyaaCategory::LiveAction(parse_subcategory(sub)?)),
+            "Pictures" => Ok(NyaaCategory::Pictures(parse_subcategory(sub)?)),
+            "Software" => Ok(NyaaCategory::Software(parse_subcategory(sub)?)),
+            _ => Err(anyhow!("Unsupported category: {}", s)),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+    use crate::source::nyaa::query_params::QueryParam;
+
+    #[rstest]
+    #[case(NyaaCategory::AllCategories, "0_0")]
+    #[case(NyaaCategory::Anime(AnimeSubCategory::MusicVideo), "1_1")]
+    #[case(NyaaCategory::Anime(AnimeSubCategory::Raw), "1_4")]
+    #[case(NyaaCategory::Audio(AudioSubCategory::Lossy), "2_2")]
+    #[case(NyaaCategory::Literature(LiteratureSubCategory::Base), "3_0")]
+    #[case(
+        NyaaCategory::Literature(LiteratureSubCategory::EnglishTranslated),
+        "3_1"
+    )]
+    #[case(
+        NyaaCategory::LiveAction(LiveActionSubCategory::IdolPromotionalVideo),
+        "4_2"
+    )]
+    #[case(NyaaCategory::Pictures(PicturesSubCategory::Photos), "5_2")]
+    #[case(NyaaCategory::Software(SoftwareSubCategory::Games), "6_2")]
+    fn test_query_param_round_trip(#[case] category: NyaaCategory, #[case] param: &str) {
+        assert_eq!(category.to_query_param(), param);
+        assert_eq!(NyaaCategory::from_query_param(param).unwrap(), category);
+        assert_eq!(
+            NyaaCategory::from_query_param(&format!("/?c={}", param)).unwrap(),
+            category
+        );
+    }
+
+    #[rstest]
+    #[case(
+        "Literature - English-translated",
+        NyaaCategory::Literature(LiteratureSubCategory::EnglishTranslated)
+    )]
+    #[case(
+        "  Anime -\n  Anime Music Video ",
+        NyaaCategory::Anime(AnimeSubCategory::MusicVideo)
+    )]
+    #[case(
+        "Live Action - Idol/Promotional Video",
+        NyaaCategory::LiveAction(LiveActionSubCategory::IdolPromotionalVideo)
+    )]
+    #[case("Software", NyaaCategory::Software(SoftwareSubCategory::Base))]
+    fn test_from_str(#[case] s: &str, #[case] expected: NyaaCategory) {
+        assert_eq!(NyaaCategory::from_str(s).unwrap(), expected);
+    }
+
+    #[rstest]
+    #[case("/?c=7_0")]
+    #[case("/?c=3_9")]
+    #[case("garbage")]
+    fn test_unknown_query_param(#[case] s: &str) {
+        assert!(NyaaCategory::from_query_param(s).is_err());
+    }
+
+    #[rstest]
+    #[case("Literature - Audiobook")]
+    #[case("Books")]
+    fn test_unknown_display_name(#[case] s: &str) {
+        assert!(NyaaCategory::from_str(s).is_err());
+    }
+}
@@ -1,9 +1,18 @@
-use super::category::{LiteratureSubCategory, NyaaCategory};
+use anyhow::{Error, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::source::{SortField, SortOrder};
+
+use super::category::{
+    AnimeSubCategory, AudioSubCategory, LiteratureSubCategory, LiveActionSubCategory, NyaaCategory,
+    PicturesSubCategory, SoftwareSubCategory,
+};
 
 pub trait QueryParam {
     fn to_query_param(&self) -> String;
 }
 
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub enum NyaaFilter {
     NoFilter,
     NoRemakes,
@@ -13,7 +22,35 @@ pub enum NyaaFilter {
 impl QueryParam for NyaaCategory {
     fn to_query_param(&self) -> String {
         match self {
+            Self::AllCategories => String::from("0_0"),
+            Self::Anime(subcategory) => format!("1_{}", subcategory.to_query_param()),
+            Self::Audio(subcategory) => format!("2_{}", subcategory.to_query_param()),
             Self::Literature(subcategory) => format!("3_{}", subcategory.to_query_param()),
+            Self::LiveAction(subcategory) => format!("4_{}", subcategory.to_query_param()),
+            Self::Pictures(subcategory) => format!("5_{}", subcategory.to_query_param()),
+            Self::Software(subcategory) => format!("6_{}", subcategory.to_query_param()),
+        }
+    }
+}
+
+impl QueryParam for AnimeSubCategory {
+    fn to_query_param(&self) -> String {
+        match self {
+            Self::Base => String::from("0"),
+            Self::MusicVideo => String::from("1"),
+            Self::EnglishTranslated => String::from("2"),
+            Self::NonEnglishTranslated => String::from("3"),
+            Self::Raw => String::from("4"),
+        }
+    }
+}
+
+impl QueryParam for AudioSubCategory {
+    fn to_query_param(&self) -> String {
+        match self {
+            Self::Base => String::from("0"),
+            Self::Lossless => String::from("1"),
+            Self::Lossy => String::from("2"),
         }
     }
 }
@@ -29,6 +66,38 @@ impl QueryParam for LiteratureSubCategory {
     }
 }
 
+impl QueryParam for LiveActionSubCategory {
+    fn to_query_param(&self) -> String {
+        match self {
+            Self::Base => String::from("0"),
+            Self::EnglishTranslated => String::from("1"),
+            Self::IdolPromotionalVideo => String::from("2"),
+            Self::NonEnglishTranslated => String::from("3"),
+            Self::Raw => String::from("4"),
+        }
+    }
+}
+
+impl QueryParam for PicturesSubCategory {
+    fn to_query_param(&self) -> String {
+        match self {
+            Self::Base => String::from("0"),
+            Self::Graphics => String::from("1"),
+            Self::Photos => String::from("2"),
+        }
+    }
+}
+
+impl QueryParam for SoftwareSubCategory {
+    fn to_query_param(&self) -> String {
+        match self {
+            Self::Base => String::from("0"),
+            Self::Applications => String::from("1"),
+            Self::Games => String::from("2"),
+        }
+    }
+}
+
 impl QueryParam for NyaaFilter {
     fn to_query_param(&self) -> String {
         match self {
@@ -38,3 +107,40 @@ impl QueryParam for NyaaFilter {
         }
     }
 }
+
+impl QueryParam for SortField {
+    fn to_query_param(&self) -> String {
+        match self {
+            Self::Date => String::from("id"),
+            Self::Size => String::from("size"),
+            Self::Comments => String::from("comments"),
+            Self::Seeders => String::from("seeders"),
+            Self::Leechers => String::from("leechers"),
+            Self::Downloads => String::from("downloads"),
+        }
+    }
+}
+
+impl QueryParam for SortOrder {
+    fn to_query_param(&self) -> String {
+        match self {
+            Self::Ascending => String::from("asc"),
+            Self::Descending => String::from("desc"),
+        }
+    }
+}
+
+// categories are (de)serialized in their query param form, e.g. "3_1"
+impl TryFrom<String> for NyaaCategory {
+    type Error = Error;
+
+    fn try_from(value: String) -> Result<Self> {
+        NyaaCategory::from_query_param(&value)
+    }
+}
+
+impl From<NyaaCategory> for String {
+    fn from(category: NyaaCategory) -> Self {
+        category.to_query_param()
+    }
+}
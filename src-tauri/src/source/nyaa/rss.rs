@@ -0,0 +1,191 @@
+use std::str::FromStr;
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use roxmltree::{Document, Node};
+
+use crate::source::{
+    nyaa::{category::NyaaCategory, Nyaa, NYAA_PAGE_SIZE},
+    FileSize, MediaInfo, PaginationInfo, ReleaseStatus,
+};
+
+const NYAA_NAMESPACE: &str = "https://nyaa.si/xmlns/nyaa";
+
+// Parsing for the nyaa rss feed (`?page=rss`).
+// The feed carries most of the listing info in structured `nyaa:` elements,
+// so it is far less brittle than scraping the html table.
+
+pub fn parse_feed(content: &str) -> Result<Vec<MediaInfo>> {
+    let document = Document::parse(content).context("Invalid rss feed")?;
+
+    let channel = document
+        .root_element()
+        .children()
+        .find(|node| node.has_tag_name("channel"))
+        .context("Missing channel in rss feed")?;
+
+    log::debug!("Parsing Nyaa rss items");
+
+    Ok(channel
+        .children()
+        .filter(|node| node.has_tag_name("item"))
+        .filter_map(|item| {
+            parse_item(item)
+                .map_err(|err| {
+                    log::warn!("{}", err);
+                    err
+                })
+                .ok()
+        })
+        .collect())
+}
+
+fn parse_item(item: Node) -> Result<MediaInfo> {
+    let title = child_text(item, None, "title").context("Missing title for item")?;
+
+    let id = child_text(item, None, "guid")
+        .context("Missing guid for item")
+        .and_then(Nyaa::extract_id_from_href)?;
+
+    let category = nyaa_text(item, "categoryId")?;
+    let size = nyaa_text(item, "size")?;
+
+    let timestamp = child_text(item, None, "pubDate").context("Missing pubDate for item")?;
+    let timestamp = DateTime::parse_from_rfc2822(timestamp)
+        .context(format!("Invalid pubDate: {}", timestamp))?
+        .with_timezone(&Utc);
+
+    let status = if nyaa_text(item, "remake")? == "Yes" {
+        ReleaseStatus::Remake
+    } else if nyaa_text(item, "trusted")? == "Yes" {
+        ReleaseStatus::Trusted
+    } else {
+        ReleaseStatus::Normal.or_batch(title)
+    };
+
+    Ok(MediaInfo {
+        id,
+        category: NyaaCategory::from_query_param(category)?.to_source_category(),
+        title: title.to_owned(),
+        size: FileSize::from_str(size)?,
+        timestamp,
+        seeders: nyaa_text(item, "seeders")?.parse()?,
+        leechers: nyaa_text(item, "leechers")?.parse()?,
+        completed: nyaa_text(item, "downloads")?.parse()?,
+        info_hash: nyaa_text(item, "infoHash")?.to_lowercase(),
+        status,
+    })
+}
+
+/// Best effort pagination since the feed does not report the number of pages
+pub fn get_pagination_info(page: u32, num_items: usize) -> PaginationInfo {
+    let has_next = num_items >= NYAA_PAGE_SIZE;
+    PaginationInfo {
+        min_page: 1,
+        max_page: if has_next { page + 1 } else { page },
+        has_prev: page > 1,
+        has_next,
+    }
+}
+
+fn child_text<'a>(item: Node<'a, '_>, namespace: Option<&str>, name: &str) -> Option<&'a str> {
+    item.children()
+        .find(|node| match namespace {
+            Some(namespace) => node.has_tag_name((namespace, name)),
+            None => node.has_tag_name(name) && node.tag_name().namespace().is_none(),
+        })
+        .and_then(|node| node.text())
+        .map(|text| text.trim())
+}
+
+fn nyaa_text<'a>(item: Node<'a, '_>, name: &str) -> Result<&'a str> {
+    child_text(item, Some(NYAA_NAMESPACE), name).context(format!("Missing nyaa:{} for item", name))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::source::{Category, SizeUnit};
+
+    use super::*;
+
+    const FEED: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<rss xmlns:atom="http://www.w3.org/2005/Atom" xmlns:nyaa="https://nyaa.si/xmlns/nyaa" version="2.0">
+  <channel>
+    <title>Nyaa - Home - Torrent File RSS</title>
+    <description>RSS Feed for Home</description>
+    <link>https://nyaa.si/</link>
+    <atom:link href="https://nyaa.si/?page=rss" rel="self" type="application/rss+xml" />
+    <item>
+      <title>My Quiet Blacksmith Life in Another World v05 (2025) (Digital) (Ushi)</title>
+      <link>https://nyaa.si/download/1990813.torrent</link>
+      <guid isPermaLink="true">https://nyaa.si/view/1990813</guid>
+      <pubDate>Thu, 10 Jul 2025 14:22:31 -0000</pubDate>
+      <nyaa:seeders>42</nyaa:seeders>
+      <nyaa:leechers>3</nyaa:leechers>
+      <nyaa:downloads>512</nyaa:downloads>
+      <nyaa:infoHash>0123456789abcdef0123456789abcdef01234567</nyaa:infoHash>
+      <nyaa:categoryId>3_1</nyaa:categoryId>
+      <nyaa:category>Literature - English-translated</nyaa:category>
+      <nyaa:size>241.3 MiB</nyaa:size>
+      <nyaa:comments>0</nyaa:comments>
+      <nyaa:trusted>Yes</nyaa:trusted>
+      <nyaa:remake>No</nyaa:remake>
+      <description><![CDATA[<a href="https://nyaa.si/view/1990813">#1990813 | My Quiet Blacksmith Life</a> | 241.3 MiB | Literature - English-translated | 0123456789ABCDEF]]></description>
+    </item>
+    <item>
+      <title>Missing everything</title>
+      <guid isPermaLink="true">https://nyaa.si/view/1990814</guid>
+    </item>
+  </channel>
+</rss>"#;
+
+    #[test]
+    fn test_parse_feed() {
+        let results = parse_feed(FEED).unwrap();
+        assert_eq!(results.len(), 1);
+
+        let info = &results[0];
+        assert_eq!(info.id, "1990813");
+        assert_eq!(
+            info.title,
+            "My Quiet Blacksmith Life in Another World v05 (2025) (Digital) (Ushi)"
+        );
+        assert!(matches!(info.category, Category::Manga));
+        assert_eq!(info.size.unit, SizeUnit::MiB);
+        assert_eq!(info.size.bytes, 253021389);
+        assert_eq!(info.timestamp.timestamp(), 1752157351);
+        assert_eq!(info.seeders, 42);
+        assert_eq!(info.leechers, 3);
+        assert_eq!(info.completed, 512);
+        assert_eq!(info.info_hash, "0123456789abcdef0123456789abcdef01234567");
+        assert_eq!(info.status, ReleaseStatus::Trusted);
+    }
+
+    #[test]
+    fn test_parse_invalid_feed() {
+        assert!(parse_feed("<html><body></body></html>").is_err());
+        assert!(parse_feed("not xml").is_err());
+    }
+
+    #[test]
+    fn test_pagination_info() {
+        assert_eq!(
+            get_pagination_info(1, NYAA_PAGE_SIZE),
+            PaginationInfo {
+                min_page: 1,
+                max_page: 2,
+                has_prev: false,
+                has_next: true,
+            }
+        );
+        assert_eq!(
+            get_pagination_info(3, 10),
+            PaginationInfo {
+                min_page: 1,
+                max_page: 3,
+                has_prev: true,
+                has_next: false,
+            }
+        );
+    }
+}
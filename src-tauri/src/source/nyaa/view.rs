@@ -0,0 +1,379 @@
+use std::str::FromStr;
+
+use anyhow::{Context, Result};
+use chrono::DateTime;
+use scraper::{ElementRef, Html, Selector};
+
+use crate::source::{
+    nyaa::{category::NyaaCategory, Nyaa},
+    Comment, FileSize, FileTreeNode, MediaInfo, ReleaseStatus, TorrentDetails,
+};
+
+// Parsing for the torrent view page (`/view/{id}`)
+
+struct NyaaViewParseConfig {
+    panel: Selector,
+    title: Selector,
+    timestamp: Selector,
+    cols: Selector,
+    link: Selector,
+    info_hash: Selector,
+    description: Selector,
+    file_list: Selector,
+    folder: Selector,
+    file_size: Selector,
+    comments: Selector,
+    comment_author: Selector,
+    comment_timestamp: Selector,
+    comment_content: Selector,
+}
+
+impl NyaaViewParseConfig {
+    pub fn new() -> Self {
+        Self {
+            panel: Selector::parse(".panel").unwrap(),
+            title: Selector::parse(".panel-title").unwrap(),
+            timestamp: Selector::parse(".panel-body [data-timestamp]").unwrap(),
+            cols: Selector::parse(".panel-body .col-md-5").unwrap(),
+            link: Selector::parse("a").unwrap(),
+            info_hash: Selector::parse(".panel-body kbd").unwrap(),
+            description: Selector::parse("#torrent-description").unwrap(),
+            file_list: Selector::parse(".torrent-file-list > ul").unwrap(),
+            folder: Selector::parse(":scope > a.folder").unwrap(),
+            file_size: Selector::parse(".file-size").unwrap(),
+            comments: Selector::parse("#comments .comment-panel").unwrap(),
+            comment_author: Selector::parse("a[href^='/user/']").unwrap(),
+            comment_timestamp: Selector::parse(".comment-details [data-timestamp]").unwrap(),
+            comment_content: Selector::parse(".comment-content").unwrap(),
+        }
+    }
+}
+
+// the info columns on the view page, in document order
+const CATEGORY_COL: usize = 0;
+const SUBMITTER_COL: usize = 2;
+const SEEDERS_COL: usize = 3;
+const WEBSITE_COL: usize = 4;
+const LEECHERS_COL: usize = 5;
+const SIZE_COL: usize = 6;
+const COMPLETED_COL: usize = 7;
+
+pub fn parse_view_page(id: &str, html: &Html) -> Result<TorrentDetails> {
+    let config = NyaaViewParseConfig::new();
+
+    let title = html
+        .select(&config.title)
+        .next()
+        .context(format!("Missing title for id: {}", id))?
+        .text()
+        .collect::<String>()
+        .trim()
+        .to_owned();
+
+    let timestamp = html
+        .select(&config.timestamp)
+        .next()
+        .context("Missing timestamp")?
+        .attr("data-timestamp")
+        .context("Missing timestamp")?
+        .parse()
+        .context("Invalid timestamp")?;
+
+    let cols = html.select(&config.cols).collect::<Vec<ElementRef>>();
+    let col = |index: usize, name: &str| {
+        cols.get(index)
+            .context(format!("Missing {} for id: {}", name, id))
+    };
+    let col_text = |index: usize, name: &str| {
+        col(index, name).map(|element| element.text().collect::<String>().trim().to_owned())
+    };
+
+    let info_hash = html
+        .select(&config.info_hash)
+        .next()
+        .context(format!("Missing info hash for id: {}", id))?
+        .text()
+        .collect::<String>()
+        .trim()
+        .to_lowercase();
+
+    let status = html
+        .select(&config.panel)
+        .next()
+        .map(|panel| Nyaa::get_release_status(&panel, "panel-"))
+        .unwrap_or(ReleaseStatus::Normal)
+        .or_batch(&title);
+
+    let info = MediaInfo {
+        id: id.to_owned(),
+        category: NyaaCategory::from_str(&col_text(CATEGORY_COL, "category")?)?
+            .to_source_category(),
+        title,
+        size: FileSize::from_str(&col_text(SIZE_COL, "file size")?)?,
+        timestamp: DateTime::from_timestamp(timestamp, 0).context("Invalid timestamp")?,
+        seeders: col_text(SEEDERS_COL, "seeders")?
+            .parse()
+            .context("failed to parse seeders")?,
+        leechers: col_text(LEECHERS_COL, "leechers")?
+            .parse()
+            .context("failed to parse leechers")?,
+        completed: col_text(COMPLETED_COL, "completed")?
+            .parse()
+            .context("failed to parse completed")?,
+        info_hash,
+        status,
+    };
+
+    let submitter = col(SUBMITTER_COL, "submitter")?
+        .select(&config.link)
+        .next()
+        .map(|link| link.text().collect::<String>().trim().to_owned());
+
+    let website = col(WEBSITE_COL, "information")?
+        .select(&config.link)
+        .next()
+        .and_then(|link| link.attr("href"))
+        .map(|href| href.to_owned());
+
+    let description = html
+        .select(&config.description)
+        .next()
+        .map(|description| description.text().collect::<String>().trim().to_owned())
+        .unwrap_or_default();
+
+    let files = html
+        .select(&config.file_list)
+        .next()
+        .map(|list| parse_file_tree(list, &config))
+        .unwrap_or_default();
+
+    let comments = html
+        .select(&config.comments)
+        .filter_map(|comment| {
+            parse_comment(comment, &config)
+                .map_err(|err| {
+                    log::warn!("{}", err);
+                    err
+                })
+                .ok()
+        })
+        .collect();
+
+    Ok(TorrentDetails {
+        info,
+        submitter,
+        website,
+        description,
+        files,
+        comments,
+    })
+}
+
+/// Walks the nested `ul > li` file list.
+/// Folders are an `a.folder` followed by a nested `ul`, files are text with a `.file-size` span.
+fn parse_file_tree(list: ElementRef, config: &NyaaViewParseConfig) -> Vec<FileTreeNode> {
+    list.children()
+        .filter_map(ElementRef::wrap)
+        .filter(|element| element.value().name() == "li")
+        .map(|item| match item.select(&config.folder).next() {
+            Some(folder) => FileTreeNode::Folder {
+                name: folder.text().collect::<String>().trim().to_owned(),
+                children: item
+                    .children()
+                    .filter_map(ElementRef::wrap)
+                    .find(|element| element.value().name() == "ul")
+                    .map(|list| parse_file_tree(list, config))
+                    .unwrap_or_default(),
+            },
+            None => FileTreeNode::File {
+                name: item
+                    .children()
+                    .filter_map(|node| node.value().as_text())
+                    .map(|text| text.to_string())
+                    .collect::<String>()
+                    .trim()
+                    .to_owned(),
+                size: item
+                    .select(&config.file_size)
+                    .next()
+                    .and_then(|size| FileSize::from_str(&size.text().collect::<String>()).ok()),
+            },
+        })
+        .collect()
+}
+
+fn parse_comment(comment: ElementRef, config: &NyaaViewParseConfig) -> Result<Comment> {
+    let author = comment
+        .select(&config.comment_author)
+        .next()
+        .context("Missing author for comment")?
+        .text()
+        .collect::<String>()
+        .trim()
+        .to_owned();
+
+    let timestamp = comment
+        .select(&config.comment_timestamp)
+        .next()
+        .context("Missing timestamp for comment")?
+        .attr("data-timestamp")
+        .context("Missing timestamp for comment")?
+        .parse()
+        .context("Invalid comment timestamp")?;
+
+    let content = comment
+        .select(&config.comment_content)
+        .next()
+        .context("Missing content for comment")?
+        .text()
+        .collect::<String>()
+        .trim()
+        .to_owned();
+
+    Ok(Comment {
+        author,
+        timestamp: DateTime::from_timestamp(timestamp, 0).context("Invalid timestamp")?,
+        content,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::source::{Category, SizeUnit};
+
+    use super::*;
+
+    const VIEW_PAGE: &str = r##"<!DOCTYPE html>
+<html>
+<body>
+<div class="container">
+  <div class="panel panel-success">
+    <div class="panel-heading">
+      <h3 class="panel-title">
+        My Quiet Blacksmith Life in Another World v05 (2025) (Digital) (Ushi)
+      </h3>
+    </div>
+    <div class="panel-body">
+      <div class="row">
+        <div class="col-md-1">Category:</div>
+        <div class="col-md-5">
+          <a href="/?c=3_0" title="Literature">Literature</a> - <a href="/?c=3_1" title="English-translated">English-translated</a>
+        </div>
+        <div class="col-md-1">Date:</div>
+        <div class="col-md-5" data-timestamp="1752157351">2025-07-10 14:22 UTC</div>
+      </div>
+      <div class="row">
+        <div class="col-md-1">Submitter:</div>
+        <div class="col-md-5"><a class="text-success" href="/user/Ushi" title="Trusted">Ushi</a></div>
+        <div class="col-md-1">Seeders:</div>
+        <div class="col-md-5"><span style="color: green;">42</span></div>
+      </div>
+      <div class="row">
+        <div class="col-md-1">Information:</div>
+        <div class="col-md-5"><a href="https://example.com/ushi">https://example.com/ushi</a></div>
+        <div class="col-md-1">Leechers:</div>
+        <div class="col-md-5"><span style="color: red;">3</span></div>
+      </div>
+      <div class="row">
+        <div class="col-md-1">File size:</div>
+        <div class="col-md-5">241.3 MiB</div>
+        <div class="col-md-1">Completed:</div>
+        <div class="col-md-5">512</div>
+      </div>
+      <div class="row">
+        <div class="col-md-offset-6 col-md-1">Info hash:</div>
+        <div class="col-md-5"><kbd>0123456789ABCDEF0123456789ABCDEF01234567</kbd></div>
+      </div>
+    </div>
+  </div>
+  <div class="panel panel-default">
+    <div markdown-text class="panel-body" id="torrent-description">**Volume 5** of the series.</div>
+  </div>
+  <div class="panel panel-default">
+    <div class="panel-heading"><h3 class="panel-title">File list</h3></div>
+    <div class="torrent-file-list panel-body">
+      <ul>
+        <li><a href="" class="folder"><i class="fa fa-folder-open"></i>My Quiet Blacksmith Life</a>
+          <ul data-show="yes">
+            <li><i class="fa fa-file"></i>v05.cbz <span class="file-size">(241.2 MiB)</span></li>
+          </ul>
+        </li>
+        <li><i class="fa fa-file"></i>readme.txt <span class="file-size">(1 KiB)</span></li>
+      </ul>
+    </div>
+  </div>
+  <div id="comments" class="panel panel-default">
+    <div class="panel-heading"><h3 class="panel-title">Comments - 1</h3></div>
+    <div class="panel panel-default comment-panel" id="com-1">
+      <div class="panel-body">
+        <div class="col-md-2"><p><a class="text-default" href="/user/reader">reader</a></p></div>
+        <div class="col-md-10 comment">
+          <div class="row comment-details">
+            <a href="#com-1"><small data-timestamp-swap data-timestamp="1752160000">2025-07-10 15:06 UTC</small></a>
+          </div>
+          <div class="row comment-body">
+            <div markdown-text class="comment-content" id="torrent-comment1">Thanks!</div>
+          </div>
+        </div>
+      </div>
+    </div>
+  </div>
+</div>
+</body>
+</html>"##;
+
+    #[test]
+    fn test_parse_view_page() {
+        let details = parse_view_page("1990813", &Html::parse_document(VIEW_PAGE)).unwrap();
+
+        assert_eq!(details.info.id, "1990813");
+        assert_eq!(
+            details.info.title,
+            "My Quiet Blacksmith Life in Another World v05 (2025) (Digital) (Ushi)"
+        );
+        assert!(matches!(details.info.category, Category::Manga));
+        assert_eq!(details.info.seeders, 42);
+        assert_eq!(details.info.leechers, 3);
+        assert_eq!(details.info.completed, 512);
+        assert_eq!(
+            details.info.info_hash,
+            "0123456789abcdef0123456789abcdef01234567"
+        );
+
+        assert_eq!(details.info.status, ReleaseStatus::Trusted);
+        assert_eq!(details.submitter.as_deref(), Some("Ushi"));
+        assert_eq!(details.website.as_deref(), Some("https://example.com/ushi"));
+        assert_eq!(details.description, "**Volume 5** of the series.");
+
+        assert_eq!(details.files.len(), 2);
+        match &details.files[0] {
+            FileTreeNode::Folder { name, children } => {
+                assert_eq!(name, "My Quiet Blacksmith Life");
+                assert!(matches!(
+                    &children[..],
+                    [FileTreeNode::File { name, size: Some(size) }]
+                        if name == "v05.cbz" && size.unit == SizeUnit::MiB
+                ));
+            }
+            file => panic!("Expected folder, found {:?}", file),
+        }
+        assert!(matches!(
+            &details.files[1],
+            FileTreeNode::File { name, size: Some(size) } if name == "readme.txt" && size.bytes == 1024
+        ));
+
+        assert_eq!(details.comments.len(), 1);
+        assert_eq!(details.comments[0].author, "reader");
+        assert_eq!(details.comments[0].content, "Thanks!");
+        assert_eq!(details.comments[0].timestamp.timestamp(), 1752160000);
+    }
+
+    #[test]
+    fn test_parse_view_page_missing_columns() {
+        let html = Html::parse_document(
+            r#"<div class="container"><div class="panel"><h3 class="panel-title">Title</h3>
+            <div class="panel-body"><div data-timestamp="1752157351"></div></div></div></div>"#,
+        );
+        assert!(parse_view_page("1", &html).is_err());
+    }
+}
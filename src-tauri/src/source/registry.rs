@@ -0,0 +1,335 @@
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Context, Result};
+use futures::{future::join_all, stream, Stream};
+
+use crate::source::{MediaInfo, PaginationInfo, SearchQuery, Source, SourceMeta, Sources};
+
+/// All the sources the app can search and download from, keyed by their variant
+#[derive(Default)]
+pub struct SourceRegistry {
+    sources: HashMap<Sources, Box<dyn Source>>,
+}
+
+impl SourceRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a source, replacing any previously registered source of the same variant
+    pub fn with_source(mut self, source: Box<dyn Source>) -> Self {
+        let variant = source.get_variant();
+        if self.sources.insert(variant, source).is_some() {
+            log::warn!("Replacing already registered source {:?}", variant);
+        }
+        self
+    }
+
+    pub fn get(&self, provider: Sources) -> Result<&dyn Source> {
+        self.sources
+            .get(&provider)
+            .map(|source| source.as_ref())
+            .context(format!("No source registered for {:?}", provider))
+    }
+
+    /// The release `url` links to, from whichever source it belongs to
+    pub fn source_from_url(&self, url: &str) -> Option<SourceMeta> {
+        self.sources.iter().find_map(|(provider, source)| {
+            Some(SourceMeta {
+                id: source.id_from_url(url)?,
+                provider: *provider,
+            })
+        })
+    }
+
+    /// Search `query.source`, or every registered source when it is not set.
+    /// When searching every source, sources that fail are skipped
+    /// as long as at least one of them succeeds.
+    pub async fn search(
+        &self,
+        query: &SearchQuery,
+    ) -> Result<(Vec<(Sources, MediaInfo)>, PaginationInfo)> {
+        if let Some(provider) = query.source {
+            let (media_info, pagination) = self.get(provider)?.search(query).await?;
+            return Ok((
+                media_info
+                    .into_iter()
+                    .map(|info| (provider, info))
+                    .collect(),
+                pagination,
+            ));
+        }
+
+        let searches = self
+            .sources
+            .iter()
+            .map(|(provider, source)| async move { (*provider, source.search(query).await) });
+
+        let mut results = vec![];
+        let mut pagination: Option<PaginationInfo> = None;
+        let mut last_err = None;
+
+        for (provider, result) in join_all(searches).await {
+            match result {
+                Ok((media_info, source_pagination)) => {
+                    results.extend(media_info.into_iter().map(|info| (provider, info)));
+                    pagination = Some(match pagination {
+                        Some(pagination) => merge_pagination(pagination, source_pagination),
+                        None => source_pagination,
+                    });
+                }
+                Err(err) => {
+                    log::warn!("Search failed for {:?}: {}", provider, err);
+                    last_err = Some(err);
+                }
+            }
+        }
+
+        match (pagination, last_err) {
+            (Some(pagination), _) => Ok((results, pagination)),
+            (None, Some(err)) => Err(err),
+            (None, None) => Err(anyhow!("No sources registered to search")),
+        }
+    }
+
+    /// Search `query` page by page, starting at `query.page`, until the last page
+    /// or `max_pages` pages have been fetched. A failed page ends the stream.
+    pub fn search_pages<'a>(
+        &'a self,
+        query: &SearchQuery,
+        max_pages: u32,
+    ) -> impl Stream<Item = Result<(Vec<(Sources, MediaInfo)>, PaginationInfo)>> + 'a {
+        let first_page = query.page.unwrap_or(1);
+        let last_page = first_page.saturating_add(max_pages.saturating_sub(1));
+
+        stream::unfold(
+            (max_pages > 0).then(|| (query.clone(), first_page)),
+            move |state| async move {
+                let (query, page) = state?;
+                let query = SearchQuery {
+                    page: Some(page),
+                    ..query
+                };
+
+                match self.search(&query).await {
+                    Ok((results, pagination)) => {
+                        let next =
+                            (pagination.has_next && page < last_page).then(|| (query, page + 1));
+                        Some((Ok((results, pagination)), next))
+                    }
+                    Err(err) => Some((Err(err), None)),
+                }
+            },
+        )
+    }
+}
+
+/// Pages line up across sources, so the combined listing goes as far as the longest one
+fn merge_pagination(a: PaginationInfo, b: PaginationInfo) -> PaginationInfo {
+    PaginationInfo {
+        min_page: a.min_page.min(b.min_page),
+        max_page: a.max_page.max(b.max_page),
+        has_prev: a.has_prev || b.has_prev,
+        has_next: a.has_next || b.has_next,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use async_trait::async_trait;
+    use chrono::DateTime;
+    use futures::StreamExt;
+    use rstest::rstest;
+
+    use crate::{
+        source::{Category, FileSize, ReleaseStatus, TorrentDetails},
+        torrent::TorrentFile,
+    };
+
+    use super::*;
+
+    struct FakeSource {
+        variant: Sources,
+        max_page: Option<u32>,
+    }
+
+    impl FakeSource {
+        /// A source with one result per search, or one that always fails when `max_page` is `None`
+        fn boxed(variant: Sources, max_page: Option<u32>) -> Box<dyn Source> {
+            Box::new(FakeSource { variant, max_page })
+        }
+    }
+
+    #[async_trait]
+    impl Source for FakeSource {
+        fn normalize_title(&self, title: &str) -> String {
+            title.to_owned()
+        }
+
+        async fn search(&self, query: &SearchQuery) -> Result<(Vec<MediaInfo>, PaginationInfo)> {
+            let max_page = self.max_page.context("Search failed")?;
+            let page = query.page.unwrap_or(1);
+            Ok((
+                vec![self.get_info_by_id(&format!("{:?}", self.variant)).await?],
+                PaginationInfo {
+                    min_page: 1,
+                    max_page,
+                    has_prev: page > 1,
+                    has_next: page < max_page,
+                },
+            ))
+        }
+
+        async fn download(
+            &self,
+            _id: &str,
+            file_path: &Path,
+            _only_files: Option<Vec<usize>>,
+        ) -> Result<PathBuf> {
+            Ok(file_path.to_path_buf())
+        }
+
+        async fn list_files(&self, _id: &str) -> Result<Vec<TorrentFile>> {
+            Ok(vec![])
+        }
+
+        async fn get_info_by_id(&self, id: &str) -> Result<MediaInfo> {
+            Ok(MediaInfo {
+                id: id.to_owned(),
+                category: Category::Manga,
+                title: id.to_owned(),
+                size: FileSize::from_bytes(0),
+                timestamp: DateTime::default(),
+                seeders: 0,
+                leechers: 0,
+                completed: 0,
+                info_hash: "".to_owned(),
+                status: ReleaseStatus::Normal,
+            })
+        }
+
+        async fn get_details_by_id(&self, _id: &str) -> Result<TorrentDetails> {
+            Err(anyhow!("No details"))
+        }
+
+        fn id_from_url(&self, url: &str) -> Option<String> {
+            url.strip_prefix(&format!("{:?}/", self.variant))
+                .map(str::to_owned)
+        }
+
+        fn get_variant(&self) -> Sources {
+            self.variant
+        }
+    }
+
+    fn ids(results: &[(Sources, MediaInfo)]) -> Vec<String> {
+        let mut ids: Vec<String> = results.iter().map(|(_, info)| info.id.clone()).collect();
+        ids.sort();
+        ids
+    }
+
+    #[tokio::test]
+    async fn test_search_single_source() {
+        let registry = SourceRegistry::new()
+            .with_source(FakeSource::boxed(Sources::Nyaa, Some(3)))
+            .with_source(FakeSource::boxed(Sources::Local, Some(1)));
+
+        let query = SearchQuery {
+            source: Some(Sources::Local),
+            ..Default::default()
+        };
+        let (results, pagination) = registry.search(&query).await.unwrap();
+        assert_eq!(ids(&results), vec!["Local"]);
+        assert_eq!(results[0].0, Sources::Local);
+        assert_eq!(pagination.max_page, 1);
+    }
+
+    #[tokio::test]
+    async fn test_search_all_sources() {
+        let registry = SourceRegistry::new()
+            .with_source(FakeSource::boxed(Sources::Nyaa, Some(3)))
+            .with_source(FakeSource::boxed(Sources::Local, Some(1)));
+
+        let (results, pagination) = registry.search(&SearchQuery::default()).await.unwrap();
+        assert_eq!(ids(&results), vec!["Local", "Nyaa"]);
+        assert_eq!(
+            pagination,
+            PaginationInfo {
+                min_page: 1,
+                max_page: 3,
+                has_prev: false,
+                has_next: true,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_search_skips_failed_sources() {
+        let registry = SourceRegistry::new()
+            .with_source(FakeSource::boxed(Sources::Nyaa, None))
+            .with_source(FakeSource::boxed(Sources::Local, Some(1)));
+
+        let (results, _) = registry.search(&SearchQuery::default()).await.unwrap();
+        assert_eq!(ids(&results), vec!["Local"]);
+
+        let registry = SourceRegistry::new().with_source(FakeSource::boxed(Sources::Nyaa, None));
+        assert!(registry.search(&SearchQuery::default()).await.is_err());
+    }
+
+    #[rstest]
+    #[case(None, 10, 3)]
+    #[case(None, 2, 2)]
+    #[case(Some(2), 10, 2)]
+    #[case(None, 0, 0)]
+    #[tokio::test]
+    async fn test_search_pages(
+        #[case] start: Option<u32>,
+        #[case] max_pages: u32,
+        #[case] expected_pages: usize,
+    ) {
+        let registry = SourceRegistry::new().with_source(FakeSource::boxed(Sources::Nyaa, Some(3)));
+        let query = SearchQuery {
+            page: start,
+            ..Default::default()
+        };
+
+        let pages: Vec<_> = registry
+            .search_pages(&query, max_pages)
+            .collect::<Vec<_>>()
+            .await
+            .into_iter()
+            .map(|page| page.unwrap())
+            .collect();
+        assert_eq!(pages.len(), expected_pages);
+    }
+
+    #[tokio::test]
+    async fn test_search_pages_stops_on_error() {
+        let registry = SourceRegistry::new().with_source(FakeSource::boxed(Sources::Nyaa, None));
+        let pages: Vec<_> = registry
+            .search_pages(&SearchQuery::default(), 10)
+            .collect()
+            .await;
+        assert_eq!(pages.len(), 1);
+        assert!(pages[0].is_err());
+    }
+
+    #[test]
+    fn test_unregistered_source() {
+        let registry = SourceRegistry::new().with_source(FakeSource::boxed(Sources::Nyaa, Some(1)));
+        assert!(registry.get(Sources::Nyaa).is_ok());
+        assert!(registry.get(Sources::Local).is_err());
+    }
+
+    #[test]
+    fn test_source_from_url() {
+        let registry = SourceRegistry::new()
+            .with_source(FakeSource::boxed(Sources::Nyaa, Some(1)))
+            .with_source(FakeSource::boxed(Sources::Local, Some(1)));
+        let source = registry.source_from_url("Local/5").unwrap();
+        assert_eq!((source.provider, source.id.as_str()), (Sources::Local, "5"));
+        assert!(registry.source_from_url("Elsewhere/5").is_none());
+    }
+}
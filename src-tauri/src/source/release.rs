@@ -0,0 +1,282 @@
+use std::sync::OnceLock;
+
+use regex::Regex;
+use serde::Serialize;
+
+/// An inclusive range of volumes, chapters or years. Single numbers have `start == end`.
+#[derive(Debug, Serialize, Clone, Copy, PartialEq)]
+pub struct NumberRange {
+    pub start: u32,
+    pub end: u32,
+}
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq)]
+pub enum ReleaseFlag {
+    Digital,
+    Completed,
+    Audiobook,
+    Raw,
+}
+
+/// A release title pulled apart into its pieces, e.g.
+/// `My Quiet Blacksmith Life in Another World v05 (2025) (Digital) (Ushi)`
+#[derive(Debug, Serialize, Clone, PartialEq, Default)]
+pub struct ParsedRelease {
+    pub title: String,
+    pub alt_titles: Vec<String>,
+    pub volumes: Option<NumberRange>,
+    pub chapters: Option<NumberRange>,
+    pub years: Option<NumberRange>,
+    pub group: Option<String>,
+    pub flags: Vec<ReleaseFlag>,
+}
+
+struct ReleasePatterns {
+    tag: Regex,
+    years: Regex,
+    volumes: Regex,
+    chapters: Regex,
+    /// Numbers without a volume or chapter prefix are chapters by convention,
+    /// but only zero padded numbers or ranges so titles like `Mob Psycho 100` are left alone
+    bare_chapters: Regex,
+    alt_title_separator: Regex,
+}
+
+fn patterns() -> &'static ReleasePatterns {
+    static PATTERNS: OnceLock<ReleasePatterns> = OnceLock::new();
+    PATTERNS.get_or_init(|| ReleasePatterns {
+        tag: Regex::new(r"\[([^\]]*)\]|\(([^)]*)\)|\{([^}]*)\}|【([^】]*)】").unwrap(),
+        years: Regex::new(r"^(?<start>\d{4})(?:\s*-\s*(?<end>\d{4}))?$").unwrap(),
+        volumes: Regex::new(
+            r"(?i)\b(?:v|vol\.?|volume)\s?(?<start>\d+)(?:\s?-\s?(?:v|vol\.?|volume)?\s?(?<end>\d+))?\b",
+        )
+        .unwrap(),
+        chapters: Regex::new(
+            r"(?i)\b(?:c|ch\.?|chapter)\s?(?<start>\d+)(?:\s?-\s?(?:c|ch\.?|chapter)?\s?(?<end>\d+))?\b",
+        )
+        .unwrap(),
+        bare_chapters: Regex::new(r"\b(?<start>\d+)(?:\s?-\s?(?<end>\d+))?\s*$").unwrap(),
+        alt_title_separator: Regex::new(r"\s[|/]\s").unwrap(),
+    })
+}
+
+fn parse_range(captures: &regex::Captures) -> Option<NumberRange> {
+    let start = captures.name("start")?.as_str().parse().ok()?;
+    let end = match captures.name("end") {
+        Some(end) => end.as_str().parse().ok()?,
+        None => start,
+    };
+    Some(NumberRange { start, end })
+}
+
+fn parse_flag(tag: &str) -> Option<ReleaseFlag> {
+    match tag.trim().to_lowercase().as_str() {
+        "digital" => Some(ReleaseFlag::Digital),
+        "completed" | "complete" => Some(ReleaseFlag::Completed),
+        "audiobook" => Some(ReleaseFlag::Audiobook),
+        "raw" => Some(ReleaseFlag::Raw),
+        _ => None,
+    }
+}
+
+impl ParsedRelease {
+    /// Whether the release spans more than one volume or chapter
+    pub fn is_batch(&self) -> bool {
+        [self.volumes, self.chapters]
+            .iter()
+            .flatten()
+            .any(|range| range.end > range.start)
+    }
+
+    pub fn parse(release: &str) -> Self {
+        let patterns = patterns();
+        let release = release.replace("’", "'");
+
+        let mut parsed = ParsedRelease::default();
+        let mut title = String::new();
+        let mut last_end = 0;
+        let mut leading_tag = None;
+        let mut trailing_tag = None;
+
+        for captures in patterns.tag.captures_iter(&release) {
+            let tag = captures.get(0).unwrap();
+            title.push_str(&release[last_end..tag.start()]);
+            last_end = tag.end();
+
+            // stylized titles like 【OSHI NO KO】 keep their content
+            if let Some(stylized) = captures.get(4) {
+                title.push_str(stylized.as_str());
+                continue;
+            }
+
+            let content = (1..=3)
+                .find_map(|i| captures.get(i))
+                .map(|content| content.as_str().trim())
+                .unwrap_or_default();
+
+            if let Some(years) = patterns.years.captures(content) {
+                parsed.years = parse_range(&years);
+            } else if let Some(flag) = parse_flag(content) {
+                if !parsed.flags.contains(&flag) {
+                    parsed.flags.push(flag);
+                }
+            } else if !content.is_empty() {
+                // groups are tagged at the end, or at the very start for some uploaders
+                if title.trim().is_empty() {
+                    leading_tag = Some(content.to_owned());
+                } else {
+                    trailing_tag = Some(content.to_owned());
+                }
+            }
+        }
+        title.push_str(&release[last_end..]);
+        parsed.group = trailing_tag.or(leading_tag);
+
+        let mut title_end = title.len();
+        if let Some(volumes) = patterns.volumes.captures(&title) {
+            parsed.volumes = parse_range(&volumes);
+            title_end = title_end.min(volumes.get(0).unwrap().start());
+        }
+        if let Some(chapters) = patterns.chapters.captures(&title) {
+            parsed.chapters = parse_range(&chapters);
+            title_end = title_end.min(chapters.get(0).unwrap().start());
+        }
+        if parsed.volumes.is_none() && parsed.chapters.is_none() {
+            if let Some(chapters) = patterns.bare_chapters.captures(title.trim_end()) {
+                if chapters.name("end").is_some() || chapters["start"].starts_with('0') {
+                    parsed.chapters = parse_range(&chapters);
+                    title_end = title_end.min(chapters.get(0).unwrap().start());
+                }
+            }
+        }
+
+        let title = title[..title_end].trim().trim_end_matches(['-', ':', ',']);
+        let mut titles = patterns
+            .alt_title_separator
+            .split(title)
+            .map(|title| title.split_whitespace().collect::<Vec<&str>>().join(" "))
+            .filter(|title| !title.is_empty());
+
+        parsed.title = titles.next().unwrap_or_default();
+        parsed.alt_titles = titles.collect();
+        parsed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    fn range(start: u32, end: u32) -> Option<NumberRange> {
+        Some(NumberRange { start, end })
+    }
+
+    #[rstest]
+    #[case(
+        "【OSHI NO KO】 001-166 (2022-2024) (Digital) (Antrill) [Completed]",
+        ParsedRelease {
+            title: "OSHI NO KO".to_owned(),
+            chapters: range(1, 166),
+            years: range(2022, 2024),
+            group: Some("Antrill".to_owned()),
+            flags: vec![ReleaseFlag::Digital, ReleaseFlag::Completed],
+            ..Default::default()
+        }
+    )]
+    #[case(
+        "The Apothecary Diaries: Xiaolan's Story 001-003 (2025) (Digital) (Oak)",
+        ParsedRelease {
+            title: "The Apothecary Diaries: Xiaolan's Story".to_owned(),
+            chapters: range(1, 3),
+            years: range(2025, 2025),
+            group: Some("Oak".to_owned()),
+            flags: vec![ReleaseFlag::Digital],
+            ..Default::default()
+        }
+    )]
+    #[case(
+        "I've Been Killing Slimes for 300 Years and Maxed Out My Level Spin-off - The Red Dragon Academy for Girls v01-02 (2023-2025) (Digital) (1r0n)",
+        ParsedRelease {
+            title: "I've Been Killing Slimes for 300 Years and Maxed Out My Level Spin-off - The Red Dragon Academy for Girls".to_owned(),
+            volumes: range(1, 2),
+            years: range(2023, 2025),
+            group: Some("1r0n".to_owned()),
+            flags: vec![ReleaseFlag::Digital],
+            ..Default::default()
+        }
+    )]
+    #[case(
+        "My Quiet Blacksmith Life in Another World v05 (2025) (Digital) (Ushi)",
+        ParsedRelease {
+            title: "My Quiet Blacksmith Life in Another World".to_owned(),
+            volumes: range(5, 5),
+            years: range(2025, 2025),
+            group: Some("Ushi".to_owned()),
+            flags: vec![ReleaseFlag::Digital],
+            ..Default::default()
+        }
+    )]
+    #[case(
+        "I’m the Evil Lord of an Intergalactic Empire! Vol 02 (Audiobook) [Troglodyte]",
+        ParsedRelease {
+            title: "I'm the Evil Lord of an Intergalactic Empire!".to_owned(),
+            volumes: range(2, 2),
+            group: Some("Troglodyte".to_owned()),
+            flags: vec![ReleaseFlag::Audiobook],
+            ..Default::default()
+        }
+    )]
+    #[case(
+        "Kaguya-sama wa Kokurasetai | Kaguya-sama: Love is War v01-28 (2017-2023) (Digital) (danke-Empire)",
+        ParsedRelease {
+            title: "Kaguya-sama wa Kokurasetai".to_owned(),
+            alt_titles: vec!["Kaguya-sama: Love is War".to_owned()],
+            volumes: range(1, 28),
+            years: range(2017, 2023),
+            group: Some("danke-Empire".to_owned()),
+            flags: vec![ReleaseFlag::Digital],
+            ..Default::default()
+        }
+    )]
+    #[case(
+        "[Raw] Sousou no Frieren Ch. 140",
+        ParsedRelease {
+            title: "Sousou no Frieren".to_owned(),
+            chapters: range(140, 140),
+            flags: vec![ReleaseFlag::Raw],
+            ..Default::default()
+        }
+    )]
+    #[case(
+        "[1r0n] Mob Psycho 100",
+        ParsedRelease {
+            title: "Mob Psycho 100".to_owned(),
+            group: Some("1r0n".to_owned()),
+            ..Default::default()
+        }
+    )]
+    fn test_parse_release(#[case] release: &str, #[case] expected: ParsedRelease) {
+        assert_eq!(ParsedRelease::parse(release), expected);
+    }
+
+    #[rstest]
+    #[case(
+        "My Quiet Blacksmith Life in Another World v05 (2025) (Digital) (Ushi)",
+        false
+    )]
+    #[case("[Raw] Sousou no Frieren Ch. 140", false)]
+    #[case("[1r0n] Mob Psycho 100", false)]
+    #[case(
+        "The Apothecary Diaries: Xiaolan's Story 001-003 (2025) (Digital) (Oak)",
+        true
+    )]
+    #[case(
+        "Kaguya-sama wa Kokurasetai v01-28 (2017-2023) (Digital) (danke-Empire)",
+        true
+    )]
+    fn test_is_batch(#[case] release: &str, #[case] expected: bool) {
+        assert_eq!(ParsedRelease::parse(release).is_batch(), expected);
+    }
+}
@@ -1,10 +1,15 @@
 use anyhow::Result;
-use serde::Serialize;
-use std::path::Path;
+use serde::{Deserialize, Serialize};
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
 use tokio::sync::watch::Receiver;
 
 use async_trait::async_trait;
+use futures::future::BoxFuture;
 
+pub mod queue;
 pub mod rqbit_service;
 
 #[derive(Clone, Serialize)]
@@ -16,25 +21,227 @@ pub struct TorrentStats {
     uploaded_bytes: u64,
     total_bytes: u64,
     finished: bool,
+    /// Uploaded bytes over the size of the torrent
+    ratio: f64,
 
     // speed is in mbps
     upload_speed: Option<f64>,
     download_speed: Option<f64>,
 
     remaining_time: Option<String>,
+
+    /// Limits in effect for this torrent, the lower of the global and per torrent limits
+    upload_limit_bps: Option<u32>,
+    download_limit_bps: Option<u32>,
+
+    /// Place in the download queue, only set while the torrent is waiting to start
+    queue_position: Option<usize>,
+}
+
+impl TorrentStats {
+    /// Placeholder stats for a download waiting in the queue
+    fn queued(id: &str, name: &str, position: usize) -> TorrentStats {
+        TorrentStats {
+            id: id.to_owned(),
+            name: name.to_owned(),
+            state: QUEUED_STATE.to_owned(),
+            progress_bytes: 0,
+            uploaded_bytes: 0,
+            total_bytes: 0,
+            finished: false,
+            ratio: 0.0,
+            upload_speed: None,
+            download_speed: None,
+            remaining_time: None,
+            upload_limit_bps: None,
+            download_limit_bps: None,
+            queue_position: Some(position),
+        }
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    pub fn queue_position(&self) -> Option<usize> {
+        self.queue_position
+    }
+
+    /// Still downloading, so it takes up one of the queue's active slots
+    fn is_active(&self) -> bool {
+        !self.finished && (self.state == "initializing" || self.state == "live")
+    }
+}
+
+pub const QUEUED_STATE: &str = "Queued";
+
+/// librqbit hands out bandwidth in 16 KiB chunks, a torrent limited below that never transfers
+pub const MIN_LIMIT_BPS: u32 = 16 * 1024;
+
+/// Transfer rate limits in bytes per second, unlimited when `None`
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct BandwidthLimits {
+    pub upload_bps: Option<u32>,
+    pub download_bps: Option<u32>,
+}
+
+impl BandwidthLimits {
+    /// Both limits apply at once, so whichever is lower wins
+    pub fn combine(&self, other: &BandwidthLimits) -> BandwidthLimits {
+        let lower = |a: Option<u32>, b: Option<u32>| match (a, b) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+
+        BandwidthLimits {
+            upload_bps: lower(self.upload_bps, other.upload_bps),
+            download_bps: lower(self.download_bps, other.download_bps),
+        }
+    }
+
+    pub fn is_unlimited(&self) -> bool {
+        self.upload_bps.is_none() && self.download_bps.is_none()
+    }
+
+    /// Raise limits below `MIN_LIMIT_BPS` to it
+    pub fn clamped(&self) -> BandwidthLimits {
+        let clamp = |limit: Option<u32>| limit.map(|limit| limit.max(MIN_LIMIT_BPS));
+        BandwidthLimits {
+            upload_bps: clamp(self.upload_bps),
+            download_bps: clamp(self.download_bps),
+        }
+    }
+}
+
+/// 0 until the size of the torrent is known
+pub fn seeding_ratio(uploaded_bytes: u64, total_bytes: u64) -> f64 {
+    if total_bytes == 0 {
+        return 0.0;
+    }
+    uploaded_bytes as f64 / total_bytes as f64
+}
+
+/// When a finished torrent stops seeding
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type")]
+pub enum SeedingPolicy {
+    /// Seed until the torrent is removed
+    #[default]
+    Unlimited,
+    /// Seed until `ratio` times the size of the torrent has been uploaded
+    Ratio {
+        ratio: f64,
+    },
+    /// Seed for `secs` seconds after finishing
+    Duration {
+        secs: u64,
+    },
+    Never,
+}
+
+impl SeedingPolicy {
+    pub fn should_stop(&self, ratio: f64, seeding_for: Duration) -> bool {
+        match self {
+            SeedingPolicy::Unlimited => false,
+            SeedingPolicy::Ratio { ratio: target } => ratio >= *target,
+            SeedingPolicy::Duration { secs } => seeding_for >= Duration::from_secs(*secs),
+            SeedingPolicy::Never => true,
+        }
+    }
+}
+
+/// A file inside a torrent, `index` is its position in the torrent's file list
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct TorrentFile {
+    pub index: usize,
+    /// Path relative to the torrent's output folder
+    pub path: String,
+    pub size: u64,
+    pub selected: bool,
+}
+
+/// Why a torrent from the last session could not be matched to a download
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(tag = "type")]
+pub enum RestoreProblem {
+    /// The folder it was downloading into is gone
+    MissingFolder,
+    /// The folder's metafile, which holds the source id, can't be read
+    UnreadableMetafile { error: String },
+    /// The session has the torrent but the session store doesn't
+    Orphaned,
+}
+
+/// A torrent from the last session that could not be restored
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct BrokenTorrent {
+    /// Id of the torrent in the session
+    pub torrent_id: usize,
+    pub name: String,
+    /// Unknown for orphaned torrents
+    pub output_dir: Option<PathBuf>,
+    pub problem: RestoreProblem,
+    /// Comment of the torrent file, sites tend to link back to the upload in it
+    pub comment: Option<String>,
+}
+
+/// Where the torrent metadata should be fetched from
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum TorrentSource {
+    Magnet(String),
+    /// Url of a .torrent file, the file is only kept in memory
+    TorrentFile(url::Url),
+    /// Try the magnet first, falling back to the .torrent file if it fails or takes too long
+    MagnetWithFallback {
+        magnet: String,
+        torrent_file: url::Url,
+    },
+}
+
+/// A torrent with its metadata fetched, so adding it doesn't have to wait on peers
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ResolvedTorrent {
+    /// Contents of the .torrent file, magnets are turned into one once resolved
+    pub torrent_bytes: Vec<u8>,
 }
 
 #[async_trait]
 pub trait TorrentService: Send + Sync {
+    /// Fetch the metadata of `source` to pass to `download_torrent`. Resolving a magnet can
+    /// take a while, so like `wait_until_finished` it doesn't borrow the service.
+    fn resolve_torrent(
+        &self,
+        id: &str,
+        source: &TorrentSource,
+    ) -> BoxFuture<'static, Result<ResolvedTorrent>>;
+
+    /// Add the torrent and start downloading it.
+    /// Only the files at the `only_files` indices are downloaded when it is set.
     async fn download_torrent(
         &mut self,
         id: &str,
-        file_url: &url::Url,
-        filename: &str,
-        base_dir: &Path,
+        torrent: &ResolvedTorrent,
+        output_dir: &Path,
+        only_files: Option<Vec<usize>>,
     ) -> Result<()>;
 
-    async fn wait_until_finished(&mut self, source_id: &str) -> Result<()>;
+    /// Files in the torrent without adding it, all of them are selected
+    async fn list_files(&self, source: &TorrentSource) -> Result<Vec<TorrentFile>>;
+
+    /// Files of an active download, selected if they are being downloaded
+    fn get_files(&self, source_id: &str) -> Result<Vec<TorrentFile>>;
+
+    /// Change which files of an active download are downloaded
+    async fn update_files(&mut self, source_id: &str, only_files: Vec<usize>) -> Result<()>;
+
+    /// Resolves once the download finishes. It doesn't borrow the service,
+    /// so nothing is held up while waiting.
+    fn wait_until_finished(&self, source_id: &str) -> Result<BoxFuture<'static, Result<()>>>;
 
     fn get_stats_receiver(&self, source_id: &str) -> Option<Receiver<TorrentStats>>;
 
@@ -43,4 +250,103 @@ pub trait TorrentService: Send + Sync {
     async fn toggle_pause(&mut self, source_id: &str) -> Result<()>;
 
     async fn remove_torrent(&mut self, source_id: &str) -> Result<()>;
+
+    /// Limits shared by every torrent
+    fn set_global_limits(&mut self, limits: BandwidthLimits);
+
+    /// Limits for a single torrent on top of the global ones, applied to an active download
+    /// right away and to the torrent whenever it is added later
+    async fn set_torrent_limits(&mut self, source_id: &str, limits: BandwidthLimits) -> Result<()>;
+
+    /// Policy for every torrent without one of its own
+    fn set_global_seeding_policy(&mut self, policy: SeedingPolicy);
+
+    /// Policy for a single torrent, `None` falls back to the global policy
+    fn set_torrent_seeding_policy(&mut self, source_id: &str, policy: Option<SeedingPolicy>);
+
+    /// Stop seeding finished torrents whose policy has been met, returning their source ids.
+    /// Torrents resumed after being stopped are left to seed.
+    async fn enforce_seeding_policies(&mut self) -> Vec<String>;
+
+    /// Torrents from the last session that could not be restored
+    fn broken_torrents(&self) -> Vec<BrokenTorrent>;
+
+    /// Restore the broken torrent `torrent_id` as the download `source_id` in `output_dir`.
+    /// It's added again, so files already there are checked rather than downloaded.
+    async fn repair_torrent(
+        &mut self,
+        torrent_id: usize,
+        source_id: &str,
+        output_dir: &Path,
+    ) -> Result<()>;
+
+    /// Drop the broken torrent `torrent_id` from the session, leaving its files in place
+    async fn forget_torrent(&mut self, torrent_id: usize) -> Result<()>;
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    #[rstest]
+    #[case((None, None), (None, None), (None, None))]
+    #[case((Some(100), None), (None, Some(50)), (Some(100), Some(50)))]
+    #[case((Some(100), Some(10)), (Some(50), Some(20)), (Some(50), Some(10)))]
+    fn test_combine_limits(
+        #[case] a: (Option<u32>, Option<u32>),
+        #[case] b: (Option<u32>, Option<u32>),
+        #[case] expected: (Option<u32>, Option<u32>),
+    ) {
+        let limits = |(upload_bps, download_bps)| BandwidthLimits {
+            upload_bps,
+            download_bps,
+        };
+        assert_eq!(limits(a).combine(&limits(b)), limits(expected));
+        assert_eq!(limits(b).combine(&limits(a)), limits(expected));
+    }
+
+    #[test]
+    fn test_clamp_limits() {
+        let limits = BandwidthLimits {
+            upload_bps: Some(1024),
+            download_bps: Some(1024 * 1024),
+        };
+        assert_eq!(
+            limits.clamped(),
+            BandwidthLimits {
+                upload_bps: Some(MIN_LIMIT_BPS),
+                download_bps: Some(1024 * 1024),
+            }
+        );
+        assert!(BandwidthLimits::default().clamped().is_unlimited());
+    }
+
+    #[rstest]
+    #[case(SeedingPolicy::Unlimited, 10.0, 1_000_000, false)]
+    #[case(SeedingPolicy::Never, 0.0, 0, true)]
+    #[case(SeedingPolicy::Ratio { ratio: 2.0 }, 1.5, 1_000_000, false)]
+    #[case(SeedingPolicy::Ratio { ratio: 2.0 }, 2.0, 0, true)]
+    #[case(SeedingPolicy::Duration { secs: 60 }, 10.0, 59, false)]
+    #[case(SeedingPolicy::Duration { secs: 60 }, 0.0, 60, true)]
+    fn test_seeding_policy(
+        #[case] policy: SeedingPolicy,
+        #[case] ratio: f64,
+        #[case] seeding_secs: u64,
+        #[case] expected: bool,
+    ) {
+        assert_eq!(
+            policy.should_stop(ratio, Duration::from_secs(seeding_secs)),
+            expected
+        );
+    }
+
+    #[rstest]
+    #[case(0, 0, 0.0)]
+    #[case(50, 100, 0.5)]
+    #[case(300, 100, 3.0)]
+    fn test_seeding_ratio(#[case] uploaded: u64, #[case] total: u64, #[case] expected: f64) {
+        assert_eq!(seeding_ratio(uploaded, total), expected);
+    }
 }
@@ -0,0 +1,609 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use async_trait::async_trait;
+use futures::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use serde_json::{from_str, to_vec};
+use tokio::{
+    fs::{create_dir, read_to_string, File},
+    io::AsyncWriteExt,
+    sync::{
+        oneshot,
+        watch::{self, Receiver, Sender},
+    },
+};
+
+use super::{
+    BandwidthLimits, BrokenTorrent, ResolvedTorrent, SeedingPolicy, TorrentFile, TorrentService,
+    TorrentSource, TorrentStats,
+};
+
+pub const DEFAULT_MAX_ACTIVE: usize = 3;
+
+const DOWNLOAD_QUEUE_FILE: &str = "download_queue.json";
+
+/// What is written to disk for each queued download, in queue order
+#[derive(Serialize, Deserialize)]
+struct SavedDownload {
+    source_id: String,
+    torrent: ResolvedTorrent,
+    output_dir: PathBuf,
+    only_files: Option<Vec<usize>>,
+}
+
+/// Receives the torrent service's wait once a queued download starts
+type Waiter = oneshot::Sender<Result<BoxFuture<'static, Result<()>>>>;
+
+struct QueuedDownload {
+    source_id: String,
+    /// Resolved before queueing, so starting it never waits on a magnet
+    torrent: ResolvedTorrent,
+    output_dir: PathBuf,
+    only_files: Option<Vec<usize>>,
+    /// Kept for the whole download so receivers handed out while queued keep getting updates
+    stats: Sender<TorrentStats>,
+    /// Callers of `wait_until_finished`, handed the wait on the torrent service once it starts
+    waiters: Mutex<Vec<Waiter>>,
+}
+
+/// Holds back downloads once `max_active` torrents are downloading and starts them in order
+/// as slots free up. Finished, paused and failed torrents don't take up a slot.
+pub struct DownloadQueue {
+    inner: Box<dyn TorrentService>,
+    max_active: usize,
+    queued: Vec<QueuedDownload>,
+    /// Where the queue is saved so it survives restarts, kept in memory only when `None`
+    path: Option<PathBuf>,
+}
+
+impl DownloadQueue {
+    pub fn new(inner: Box<dyn TorrentService>) -> Self {
+        DownloadQueue {
+            inner,
+            max_active: DEFAULT_MAX_ACTIVE,
+            queued: vec![],
+            path: None,
+        }
+    }
+
+    /// Save the queue in `dir`, picking up any downloads that were still queued there
+    pub async fn with_saved_queue(mut self, dir: &Path) -> Result<Self> {
+        let path = dir.join(DOWNLOAD_QUEUE_FILE);
+        let saved: Vec<SavedDownload> = if path.exists() {
+            let content = read_to_string(&path).await?;
+            from_str(&content).context(format!("Invalid download queue: {}", path.display()))?
+        } else {
+            vec![]
+        };
+
+        log::info!("Found {} queued downloads", saved.len());
+        for download in saved {
+            self.enqueue(
+                &download.source_id,
+                download.torrent,
+                download.output_dir,
+                download.only_files,
+            );
+        }
+        self.path = Some(path);
+        Ok(self)
+    }
+
+    async fn write(&self) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+
+        let saved: Vec<SavedDownload> = self
+            .queued
+            .iter()
+            .map(|download| SavedDownload {
+                source_id: download.source_id.clone(),
+                torrent: download.torrent.clone(),
+                output_dir: download.output_dir.clone(),
+                only_files: download.only_files.clone(),
+            })
+            .collect();
+
+        let mut file = File::create(path).await?;
+        file.write_all(to_vec(&saved)?.as_slice()).await?;
+        file.flush().await?;
+        log::trace!("Successfully wrote download queue to {}", path.display());
+        Ok(())
+    }
+
+    fn enqueue(
+        &mut self,
+        source_id: &str,
+        torrent: ResolvedTorrent,
+        output_dir: PathBuf,
+        only_files: Option<Vec<usize>>,
+    ) {
+        let name = output_dir
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let (stats, _) = watch::channel(TorrentStats::queued(source_id, &name, self.queued.len()));
+        self.queued.push(QueuedDownload {
+            source_id: source_id.to_owned(),
+            torrent,
+            output_dir,
+            only_files,
+            stats,
+            waiters: Mutex::new(vec![]),
+        });
+    }
+
+    pub fn with_max_active(mut self, max_active: usize) -> Self {
+        self.max_active = max_active.max(1);
+        self
+    }
+
+    pub async fn set_max_active(&mut self, max_active: usize) {
+        log::info!("Allowing {} active downloads", max_active);
+        self.max_active = max_active.max(1);
+        self.promote().await;
+    }
+
+    fn active_count(&self) -> usize {
+        self.inner
+            .list_torrents()
+            .iter()
+            .filter(|stats| stats.is_active())
+            .count()
+    }
+
+    fn position(&self, source_id: &str) -> Option<usize> {
+        self.queued
+            .iter()
+            .position(|download| download.source_id == source_id)
+    }
+
+    fn update_positions(&self) {
+        for (position, download) in self.queued.iter().enumerate() {
+            download
+                .stats
+                .send_modify(|stats| stats.queue_position = Some(position));
+        }
+    }
+
+    /// Move the queued download `source_id` to `position`, 0 being the next one to start
+    pub async fn move_queued(&mut self, source_id: &str, position: usize) -> Result<()> {
+        let index = self
+            .position(source_id)
+            .context(format!("{} is not queued", source_id))?;
+
+        let download = self.queued.remove(index);
+        let position = position.min(self.queued.len());
+        log::info!("Moving queued download {} to {}", source_id, position);
+        self.queued.insert(position, download);
+        self.update_positions();
+        self.write().await
+    }
+
+    /// Start queued downloads in order while there are free slots
+    pub async fn promote(&mut self) {
+        let queued = self.queued.len();
+        while !self.queued.is_empty() && self.active_count() < self.max_active {
+            let download = self.queued.remove(0);
+            log::info!("Starting queued download {}", download.source_id);
+
+            let result = self
+                .inner
+                .download_torrent(
+                    &download.source_id,
+                    &download.torrent,
+                    &download.output_dir,
+                    download.only_files,
+                )
+                .await;
+
+            let waiters = download.waiters.into_inner().unwrap();
+            match result {
+                Ok(()) => {
+                    for waiter in waiters {
+                        let _ = waiter.send(self.inner.wait_until_finished(&download.source_id));
+                    }
+                    if let Some(rx) = self.inner.get_stats_receiver(&download.source_id) {
+                        forward_stats(rx, download.stats);
+                    }
+                }
+                Err(err) => {
+                    log::error!(
+                        "Failed to start queued download {}: {}",
+                        download.source_id,
+                        err
+                    );
+                    download.stats.send_modify(|stats| {
+                        stats.state = "error".to_owned();
+                        stats.queue_position = None;
+                    });
+                    for waiter in waiters {
+                        let _ = waiter.send(Err(anyhow!(
+                            "{} failed to start: {}",
+                            download.source_id,
+                            err
+                        )));
+                    }
+                }
+            }
+            self.update_positions();
+        }
+
+        if self.queued.len() != queued {
+            if let Err(err) = self.write().await {
+                log::error!("Failed to save download queue: {}", err);
+            }
+        }
+    }
+}
+
+/// Pass stats from the started torrent on to receivers handed out while it was queued
+fn forward_stats(mut rx: Receiver<TorrentStats>, tx: Sender<TorrentStats>) {
+    tokio::spawn(async move {
+        loop {
+            let stats = rx.borrow_and_update().clone();
+            if tx.send(stats).is_err() || rx.changed().await.is_err() {
+                break;
+            }
+        }
+    });
+}
+
+#[async_trait]
+impl TorrentService for DownloadQueue {
+    fn resolve_torrent(
+        &self,
+        id: &str,
+        source: &TorrentSource,
+    ) -> BoxFuture<'static, Result<ResolvedTorrent>> {
+        self.inner.resolve_torrent(id, source)
+    }
+
+    async fn download_torrent(
+        &mut self,
+        id: &str,
+        torrent: &ResolvedTorrent,
+        output_dir: &Path,
+        only_files: Option<Vec<usize>>,
+    ) -> Result<()> {
+        self.promote().await;
+        if self.position(id).is_some() {
+            bail!("{} is already queued", id);
+        }
+
+        if self.queued.is_empty() && self.active_count() < self.max_active {
+            return self
+                .inner
+                .download_torrent(id, torrent, output_dir, only_files)
+                .await;
+        }
+
+        log::info!(
+            "{} downloads active, queueing {} at {}",
+            self.max_active,
+            id,
+            self.queued.len()
+        );
+        // the metafile is written next to the download before it gets to start
+        if !output_dir.exists() {
+            create_dir(output_dir)
+                .await
+                .context(format!("Could not create {}", output_dir.display()))?;
+        }
+        self.enqueue(id, torrent.clone(), output_dir.to_owned(), only_files);
+        self.write().await
+    }
+
+    async fn list_files(&self, source: &TorrentSource) -> Result<Vec<TorrentFile>> {
+        self.inner.list_files(source).await
+    }
+
+    fn get_files(&self, source_id: &str) -> Result<Vec<TorrentFile>> {
+        if self.position(source_id).is_some() {
+            bail!("{} is queued and hasn't started yet", source_id);
+        }
+        self.inner.get_files(source_id)
+    }
+
+    async fn update_files(&mut self, source_id: &str, only_files: Vec<usize>) -> Result<()> {
+        if let Some(index) = self.position(source_id) {
+            self.queued[index].only_files = Some(only_files);
+            return self.write().await;
+        }
+        self.inner.update_files(source_id, only_files).await
+    }
+
+    fn wait_until_finished(&self, source_id: &str) -> Result<BoxFuture<'static, Result<()>>> {
+        let Some(index) = self.position(source_id) else {
+            return self.inner.wait_until_finished(source_id);
+        };
+
+        let (tx, rx) = oneshot::channel();
+        self.queued[index].waiters.lock().unwrap().push(tx);
+        let source_id = source_id.to_owned();
+        Ok(Box::pin(async move {
+            let finished = rx.await.map_err(|_| {
+                anyhow!("{} was removed from the queue before it started", source_id)
+            })??;
+            finished.await
+        }))
+    }
+
+    fn get_stats_receiver(&self, source_id: &str) -> Option<Receiver<TorrentStats>> {
+        match self.position(source_id) {
+            Some(index) => Some(self.queued[index].stats.subscribe()),
+            None => self.inner.get_stats_receiver(source_id),
+        }
+    }
+
+    fn list_torrents(&self) -> Vec<TorrentStats> {
+        let mut torrents = self.inner.list_torrents();
+        torrents.extend(
+            self.queued
+                .iter()
+                .map(|download| download.stats.borrow().clone()),
+        );
+        torrents
+    }
+
+    async fn toggle_pause(&mut self, source_id: &str) -> Result<()> {
+        if self.position(source_id).is_some() {
+            bail!("{} is queued and hasn't started yet", source_id);
+        }
+        self.inner.toggle_pause(source_id).await?;
+        self.promote().await;
+        Ok(())
+    }
+
+    async fn remove_torrent(&mut self, source_id: &str) -> Result<()> {
+        if let Some(index) = self.position(source_id) {
+            log::info!("Removing {} from the download queue", source_id);
+            self.queued.remove(index);
+            self.update_positions();
+            return self.write().await;
+        }
+        self.inner.remove_torrent(source_id).await?;
+        self.promote().await;
+        Ok(())
+    }
+
+    fn set_global_limits(&mut self, limits: BandwidthLimits) {
+        self.inner.set_global_limits(limits)
+    }
+
+    async fn set_torrent_limits(&mut self, source_id: &str, limits: BandwidthLimits) -> Result<()> {
+        // limits for torrents that aren't active yet are kept and applied once they are added
+        self.inner.set_torrent_limits(source_id, limits).await
+    }
+
+    fn set_global_seeding_policy(&mut self, policy: SeedingPolicy) {
+        self.inner.set_global_seeding_policy(policy)
+    }
+
+    fn set_torrent_seeding_policy(&mut self, source_id: &str, policy: Option<SeedingPolicy>) {
+        self.inner.set_torrent_seeding_policy(source_id, policy)
+    }
+
+    async fn enforce_seeding_policies(&mut self) -> Vec<String> {
+        self.inner.enforce_seeding_policies().await
+    }
+
+    fn broken_torrents(&self) -> Vec<BrokenTorrent> {
+        self.inner.broken_torrents()
+    }
+
+    async fn repair_torrent(
+        &mut self,
+        torrent_id: usize,
+        source_id: &str,
+        output_dir: &Path,
+    ) -> Result<()> {
+        if self.position(source_id).is_some() {
+            bail!("{} is already queued", source_id);
+        }
+        self.inner
+            .repair_torrent(torrent_id, source_id, output_dir)
+            .await
+    }
+
+    async fn forget_torrent(&mut self, torrent_id: usize) -> Result<()> {
+        self.inner.forget_torrent(torrent_id).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use tempdir::TempDir;
+
+    use crate::torrent::{rqbit_service::MockRqbitService, QUEUED_STATE};
+
+    use super::*;
+
+    fn stats(id: &str, finished: bool) -> TorrentStats {
+        TorrentStats {
+            state: "live".to_owned(),
+            finished,
+            queue_position: None,
+            ..TorrentStats::queued(id, id, 0)
+        }
+    }
+
+    /// A torrent service where every download stays active until it is marked finished
+    fn mock_service(torrents: Arc<Mutex<Vec<TorrentStats>>>) -> MockRqbitService {
+        let mut service = MockRqbitService::new();
+        service.expect_list_torrents().returning({
+            let torrents = torrents.clone();
+            move || torrents.lock().unwrap().clone()
+        });
+        service
+            .expect_download_torrent()
+            .returning(move |id, _, _, _| {
+                torrents.lock().unwrap().push(stats(id, false));
+                Ok(())
+            });
+        service.expect_get_stats_receiver().returning(|_| None);
+        service
+    }
+
+    async fn download(queue: &mut DownloadQueue, dir: &TempDir, id: &str) {
+        let torrent = ResolvedTorrent {
+            torrent_bytes: id.as_bytes().to_vec(),
+        };
+        queue
+            .download_torrent(id, &torrent, &dir.path().join(id), None)
+            .await
+            .unwrap();
+    }
+
+    fn states(queue: &DownloadQueue) -> Vec<(String, String, Option<usize>)> {
+        queue
+            .list_torrents()
+            .into_iter()
+            .map(|stats| (stats.id, stats.state, stats.queue_position))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_download_queue() {
+        let dir = TempDir::new("queue").unwrap();
+        let torrents = Arc::new(Mutex::new(vec![]));
+        let mut queue =
+            DownloadQueue::new(Box::new(mock_service(torrents.clone()))).with_max_active(1);
+
+        for id in ["1", "2", "3"] {
+            download(&mut queue, &dir, id).await;
+        }
+        assert_eq!(
+            states(&queue),
+            vec![
+                ("1".to_owned(), "live".to_owned(), None),
+                ("2".to_owned(), "Queued".to_owned(), Some(0)),
+                ("3".to_owned(), "Queued".to_owned(), Some(1)),
+            ]
+        );
+        assert!(dir.path().join("3").is_dir());
+
+        queue.move_queued("3", 0).await.unwrap();
+        assert!(queue.move_queued("1", 0).await.is_err());
+
+        // nothing starts until the active download finishes
+        queue.promote().await;
+        assert_eq!(
+            states(&queue)[1],
+            ("3".to_owned(), "Queued".to_owned(), Some(0))
+        );
+
+        torrents.lock().unwrap()[0].finished = true;
+        queue.promote().await;
+        assert_eq!(
+            states(&queue),
+            vec![
+                ("1".to_owned(), "live".to_owned(), None),
+                ("3".to_owned(), "live".to_owned(), None),
+                ("2".to_owned(), "Queued".to_owned(), Some(0)),
+            ]
+        );
+
+        queue.set_max_active(2).await;
+        assert!(states(&queue)
+            .iter()
+            .all(|(_, state, _)| state != QUEUED_STATE));
+    }
+
+    #[tokio::test]
+    async fn test_remove_queued_download() {
+        let dir = TempDir::new("queue").unwrap();
+        let torrents = Arc::new(Mutex::new(vec![]));
+        let mut queue =
+            DownloadQueue::new(Box::new(mock_service(torrents.clone()))).with_max_active(1);
+
+        for id in ["1", "2", "3"] {
+            download(&mut queue, &dir, id).await;
+        }
+        let mut rx = queue.get_stats_receiver("3").unwrap();
+
+        queue.remove_torrent("2").await.unwrap();
+        assert_eq!(rx.borrow_and_update().queue_position, Some(0));
+        assert_eq!(queue.list_torrents().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_saved_queue() {
+        let dir = TempDir::new("queue").unwrap();
+        let torrents = Arc::new(Mutex::new(vec![]));
+        let mut queue = DownloadQueue::new(Box::new(mock_service(torrents.clone())))
+            .with_max_active(1)
+            .with_saved_queue(dir.path())
+            .await
+            .unwrap();
+
+        for id in ["1", "2", "3"] {
+            download(&mut queue, &dir, id).await;
+        }
+        queue.move_queued("3", 0).await.unwrap();
+        drop(queue);
+
+        // the first download was already handed to the torrent service, which restores it itself
+        let queue = DownloadQueue::new(Box::new(mock_service(torrents)))
+            .with_max_active(1)
+            .with_saved_queue(dir.path())
+            .await
+            .unwrap();
+        assert_eq!(
+            states(&queue),
+            vec![
+                ("1".to_owned(), "live".to_owned(), None),
+                ("3".to_owned(), "Queued".to_owned(), Some(0)),
+                ("2".to_owned(), "Queued".to_owned(), Some(1)),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_queued_download() {
+        let dir = TempDir::new("queue").unwrap();
+        let torrents = Arc::new(Mutex::new(vec![]));
+        let mut service = mock_service(torrents.clone());
+        service
+            .expect_wait_until_finished()
+            .withf(|id| id == "2")
+            .times(1)
+            .returning(|_| Ok(Box::pin(async { Ok(()) })));
+        let mut queue = DownloadQueue::new(Box::new(service)).with_max_active(1);
+
+        for id in ["1", "2", "3"] {
+            download(&mut queue, &dir, id).await;
+        }
+        let started = queue.wait_until_finished("2").unwrap();
+        let removed = queue.wait_until_finished("3").unwrap();
+
+        torrents.lock().unwrap()[0].finished = true;
+        queue.promote().await;
+        started.await.unwrap();
+
+        queue.remove_torrent("3").await.unwrap();
+        assert!(removed.await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_repair_into_queued_download() {
+        let dir = TempDir::new("queue").unwrap();
+        let torrents = Arc::new(Mutex::new(vec![]));
+        let mut service = mock_service(torrents.clone());
+        service.expect_repair_torrent().never();
+        let mut queue = DownloadQueue::new(Box::new(service)).with_max_active(1);
+
+        for id in ["1", "2"] {
+            download(&mut queue, &dir, id).await;
+        }
+
+        assert!(queue.repair_torrent(5, "2", dir.path()).await.is_err());
+    }
+}
@@ -1,36 +1,188 @@
-use anyhow::{Context, Ok, Result};
+use anyhow::{bail, Context, Ok, Result};
 use async_trait::async_trait;
-use librqbit::{api::TorrentIdOrHash, AddTorrent, AddTorrentOptions, ManagedTorrent};
+use chrono::{DateTime, Utc};
+use futures::future::BoxFuture;
+use librqbit::{
+    api::TorrentIdOrHash, limits::LimitsConfig, torrent_from_bytes, AddTorrent, AddTorrentOptions,
+    AddTorrentResponse, ByteBuf, ManagedTorrent, TorrentMetaV1Info,
+};
 
 use log::info;
 #[cfg(test)]
 use mockall::automock;
-use serde::Deserialize;
-use serde_json::from_str;
+use serde::{Deserialize, Serialize};
+use serde_json::{from_str, to_vec};
 
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
+    num::NonZeroU32,
     path::{Path, PathBuf},
-    sync::Arc,
+    sync::{Arc, RwLock},
     time::Duration,
 };
 use tokio::{
-    fs::{create_dir, read_to_string},
+    fs::{create_dir, create_dir_all, read_to_string, File},
+    io::AsyncWriteExt,
     sync::watch::{self, Receiver},
 };
 
 use crate::{
+    http::HttpClient,
     metafile::Metafile,
-    torrent::{TorrentService, TorrentStats},
-    utils::download_file_from_url,
+    torrent::{
+        seeding_ratio, BandwidthLimits, BrokenTorrent, ResolvedTorrent, RestoreProblem,
+        SeedingPolicy, TorrentFile, TorrentService, TorrentSource, TorrentStats,
+    },
 };
 
+const FINISHED_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+pub const DEFAULT_MAGNET_TIMEOUT: Duration = Duration::from_secs(60);
+/// Kept next to the session store
+const UPLOADS_FILE: &str = "uploads.json";
+
 pub struct RqbitService {
     session: Arc<librqbit::Session>,
-    client: reqwest::Client,
+    client: HttpClient,
     handles: HashMap<String, Arc<ManagedTorrent>>,
     receivers: HashMap<String, Receiver<TorrentStats>>,
     id_translation: HashMap<usize, String>, // torrent id to source id
+    /// Folder each torrent downloads into, so it can be added again with new options
+    output_dirs: HashMap<String, PathBuf>,
+    limits: Arc<RwLock<RateLimits>>,
+    seeding: SeedingPolicies,
+    /// Torrents from the last session that couldn't be restored, keyed by torrent id
+    broken: HashMap<usize, BrokenTorrent>,
+    /// How long resolving a magnet can take before giving up on it
+    magnet_timeout: Duration,
+}
+
+/// Limits set on the service, shared with the tasks reporting stats
+#[derive(Default)]
+struct RateLimits {
+    global: BandwidthLimits,
+    torrents: HashMap<String, BandwidthLimits>,
+}
+
+impl RateLimits {
+    fn effective(&self, source_id: &str) -> BandwidthLimits {
+        match self.torrents.get(source_id) {
+            Some(limits) => self.global.combine(limits),
+            None => self.global,
+        }
+    }
+}
+
+#[derive(Default)]
+struct SeedingPolicies {
+    global: SeedingPolicy,
+    torrents: HashMap<String, SeedingPolicy>,
+    /// Upload history of each torrent, shared with the tasks reporting stats
+    uploads: Arc<RwLock<HashMap<String, UploadRecord>>>,
+    /// Where `uploads` is saved so it survives restarts
+    uploads_path: PathBuf,
+    /// Torrents already stopped by their policy, they keep seeding if they are resumed
+    stopped: HashSet<String>,
+}
+
+impl SeedingPolicies {
+    fn effective(&self, source_id: &str) -> SeedingPolicy {
+        self.torrents.get(source_id).copied().unwrap_or(self.global)
+    }
+
+    fn forget(&mut self, source_id: &str) {
+        self.uploads.write().unwrap().remove(source_id);
+        self.stopped.remove(source_id);
+    }
+
+    fn upload_record(&self, source_id: &str) -> UploadRecord {
+        self.uploads
+            .read()
+            .unwrap()
+            .get(source_id)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Call before the live upload counter of `handle` starts over, e.g. it's paused or added again
+    fn checkpoint(&self, source_id: &str, handle: &ManagedTorrent) {
+        self.uploads
+            .write()
+            .unwrap()
+            .entry(source_id.to_owned())
+            .or_default()
+            .checkpoint(handle.stats().uploaded_bytes);
+    }
+}
+
+/// Upload history of a torrent. librqbit counts uploads per session and starts from 0
+/// whenever a torrent is resumed or added again, so earlier uploads are kept here.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+struct UploadRecord {
+    /// When the torrent was first seen finished
+    seeding_since: Option<DateTime<Utc>>,
+    /// Bytes uploaded before the live counter last started over
+    uploaded_bytes: u64,
+    /// Last value seen on the live counter
+    #[serde(skip)]
+    live_uploaded_bytes: u64,
+}
+
+impl UploadRecord {
+    /// Everything uploaded so far, given the current value of the live counter
+    fn total(&self, live_uploaded_bytes: u64) -> u64 {
+        if live_uploaded_bytes < self.live_uploaded_bytes {
+            // started over since it was last seen
+            self.uploaded_bytes + self.live_uploaded_bytes + live_uploaded_bytes
+        } else {
+            self.uploaded_bytes + live_uploaded_bytes
+        }
+    }
+
+    fn update(&mut self, live_uploaded_bytes: u64) {
+        self.uploaded_bytes = self.total(live_uploaded_bytes) - live_uploaded_bytes;
+        self.live_uploaded_bytes = live_uploaded_bytes;
+    }
+
+    fn checkpoint(&mut self, live_uploaded_bytes: u64) {
+        self.uploaded_bytes = self.total(live_uploaded_bytes);
+        self.live_uploaded_bytes = 0;
+    }
+}
+
+async fn read_upload_records(path: &Path) -> HashMap<String, UploadRecord> {
+    if !path.exists() {
+        return HashMap::new();
+    }
+
+    match read_to_string(path)
+        .await
+        .map_err(anyhow::Error::from)
+        .and_then(|content| Ok(from_str(&content)?))
+    {
+        Result::Ok(records) => records,
+        Err(err) => {
+            log::error!("Failed to read uploads from {}: {:#}", path.display(), err);
+            HashMap::new()
+        }
+    }
+}
+
+/// Records are written with the live counters folded in, they start from 0 on the next launch
+async fn write_upload_records(path: &Path, records: &HashMap<String, UploadRecord>) -> Result<()> {
+    let records: HashMap<&String, UploadRecord> = records
+        .iter()
+        .map(|(source_id, record)| {
+            let mut record = *record;
+            record.checkpoint(record.live_uploaded_bytes);
+            (source_id, record)
+        })
+        .collect();
+
+    let mut file = File::create(path).await?;
+    file.write_all(to_vec(&records)?.as_slice()).await?;
+    file.flush().await?;
+    log::trace!("Successfully wrote uploads to {}", path.display());
+    Ok(())
 }
 
 #[derive(Deserialize)]
@@ -43,97 +195,353 @@ struct SerializedSessionDatabase {
     torrents: HashMap<usize, SerializedTorrent>,
 }
 
+/// What could be recovered from the session store
+#[derive(Default)]
+struct RestoredSession {
+    id_translation: HashMap<usize, String>,
+    output_dirs: HashMap<String, PathBuf>,
+    broken: HashMap<usize, BrokenTorrent>,
+}
+
 impl RqbitService {
     pub async fn new(
         session: Arc<librqbit::Session>,
-        client: reqwest::Client,
+        client: HttpClient,
         session_store_path: &Path,
     ) -> Self {
+        let restored = RqbitService::restore_session(session_store_path).await;
+        let uploads_path = session_store_path.with_file_name(UPLOADS_FILE);
+        let seeding = SeedingPolicies {
+            uploads: Arc::new(RwLock::new(read_upload_records(&uploads_path).await)),
+            uploads_path,
+            ..Default::default()
+        };
         let mut instance = Self {
             session,
             client,
             handles: HashMap::new(),
             receivers: HashMap::new(),
-            id_translation: RqbitService::restore_id_translation(session_store_path).await,
+            id_translation: restored.id_translation,
+            output_dirs: restored.output_dirs,
+            limits: Arc::new(RwLock::new(RateLimits::default())),
+            seeding,
+            broken: restored.broken,
+            magnet_timeout: DEFAULT_MAGNET_TIMEOUT,
         };
         instance.restore_handles();
         instance
     }
 
-    fn restore_handles(&mut self) -> () {
-        let restored_handles = self.session.with_torrents(|torrents| {
-            let mut handles = HashMap::new();
-            torrents.for_each(|(id, handle)| {
-                let source_id = self
-                    .id_translation
-                    .get(&id)
-                    .expect("missing source id for torrent");
-                handles.insert(source_id.to_owned(), handle.clone());
-            });
-            handles
-        });
+    pub fn with_magnet_timeout(mut self, magnet_timeout: Duration) -> Self {
+        self.magnet_timeout = magnet_timeout;
+        self
+    }
 
-        self.handles = restored_handles;
+    /// Limits for single torrents saved from the last session. librqbit doesn't keep them across
+    /// restarts, so restored torrents with limits are added again with them straight away,
+    /// while they are still being checked from the restore rather than once that check is done.
+    pub async fn with_torrent_limits(mut self, torrents: HashMap<String, BandwidthLimits>) -> Self {
+        for (source_id, limits) in torrents {
+            if let Err(err) = self.set_torrent_limits(&source_id, limits).await {
+                log::warn!("Failed to apply limits for {}: {}", source_id, err);
+            }
+        }
+        self
     }
 
-    async fn restore_id_translation(session_store_path: &Path) -> HashMap<usize, String> {
-        let mut id_translation = HashMap::new();
-        if session_store_path.exists() {
-            let content = read_to_string(session_store_path)
-                .await
-                .expect("Session store to be present");
-            let serialized_torrents: SerializedSessionDatabase =
-                from_str(&content).expect("session.json to be a serialized torrent");
+    /// Resolve a magnet into a torrent file, giving up if it takes longer than `magnet_timeout`
+    async fn resolve_magnet(
+        session: &Arc<librqbit::Session>,
+        source_id: &str,
+        uri: &str,
+        magnet_timeout: Duration,
+    ) -> Result<ResolvedTorrent> {
+        let options = AddTorrentOptions {
+            list_only: true,
+            ..Default::default()
+        };
+        let add = session.add_torrent(AddTorrent::from_url(uri.to_owned()), Some(options));
+        let response = tokio::time::timeout(magnet_timeout, add)
+            .await
+            .context(format!(
+                "Timed out resolving the magnet for {} after {:?}",
+                source_id, magnet_timeout
+            ))??;
 
-            for (id, torrent) in serialized_torrents.torrents.iter() {
-                let metafile = Metafile::read(&torrent.output_folder)
-                    .await
-                    .expect(".metafile to be valid");
+        match response {
+            AddTorrentResponse::ListOnly(response) => Ok(ResolvedTorrent {
+                torrent_bytes: response.torrent_bytes.to_vec(),
+            }),
+            _ => bail!("Expected only the metadata of {}", uri),
+        }
+    }
 
-                id_translation.insert(id.to_owned(), metafile.source.id);
+    /// Match the session's torrents to their downloads, anything that can't be is kept as broken
+    fn restore_handles(&mut self) {
+        let torrents: Vec<(usize, Arc<ManagedTorrent>)> = self
+            .session
+            .with_torrents(|torrents| torrents.map(|(id, handle)| (id, handle.clone())).collect());
+
+        // the session store can list torrents the session failed to load
+        self.broken
+            .retain(|id, _| torrents.iter().any(|(torrent_id, _)| torrent_id == id));
+
+        for (id, handle) in torrents {
+            if let Some(source_id) = self.id_translation.get(&id) {
+                self.handles.insert(source_id.to_owned(), handle);
+                continue;
             }
+
+            let broken = self.broken.entry(id).or_insert_with(|| {
+                log::warn!("Torrent {} is missing from the session store", id);
+                BrokenTorrent {
+                    torrent_id: id,
+                    name: "".to_owned(),
+                    output_dir: None,
+                    problem: RestoreProblem::Orphaned,
+                    comment: None,
+                }
+            });
+            broken.name = handle.name().unwrap_or_default();
+            broken.comment = torrent_comment(&handle);
         }
 
-        id_translation
+        if !self.broken.is_empty() {
+            log::warn!("{} torrents could not be restored", self.broken.len());
+        }
     }
 
-    async fn download_torrent_file(
-        &self,
-        file_url: &url::Url,
-        filename: &str,
-        base_dir: &Path,
-    ) -> Result<PathBuf> {
-        let output_path = base_dir.join(&filename);
-
-        log::debug!(
-            "Downloading torrent file from {} to {}",
-            file_url,
-            output_path.display()
-        );
-        download_file_from_url(&self.client, &file_url, &filename, base_dir)
+    /// Source ids of the persisted torrents, read from the metafile in the folder each of them
+    /// downloads into
+    async fn restore_session(session_store_path: &Path) -> RestoredSession {
+        let mut restored = RestoredSession::default();
+        if !session_store_path.exists() {
+            return restored;
+        }
+
+        let serialized_torrents = match read_to_string(session_store_path)
             .await
-            .map_err(|err| {
-                log::error!("Failed to download torrent file from {}", &file_url);
-                err
-            })?;
-        log::debug!(
-            "Finished downloading torrent file from {} to {}",
-            file_url,
-            base_dir.display()
-        );
-        Ok(output_path)
+            .map_err(anyhow::Error::from)
+            .and_then(|content| Ok(from_str::<SerializedSessionDatabase>(&content)?))
+        {
+            Result::Ok(serialized_torrents) => serialized_torrents,
+            Err(err) => {
+                log::error!(
+                    "Failed to read session store {}: {:#}",
+                    session_store_path.display(),
+                    err
+                );
+                return restored;
+            }
+        };
+
+        for (id, torrent) in serialized_torrents.torrents {
+            let output_dir = torrent.output_folder;
+            let problem = if !output_dir.exists() {
+                RestoreProblem::MissingFolder
+            } else {
+                match Metafile::read(&output_dir).await {
+                    Result::Ok(metafile) => {
+                        restored
+                            .output_dirs
+                            .insert(metafile.source.id.clone(), output_dir);
+                        restored.id_translation.insert(id, metafile.source.id);
+                        continue;
+                    }
+                    Err(err) => RestoreProblem::UnreadableMetafile {
+                        error: format!("{:#}", err),
+                    },
+                }
+            };
+
+            log::warn!(
+                "Can't restore torrent {} in {}: {:?}",
+                id,
+                output_dir.display(),
+                problem
+            );
+            restored.broken.insert(
+                id,
+                BrokenTorrent {
+                    torrent_id: id,
+                    name: "".to_owned(),
+                    output_dir: Some(output_dir),
+                    problem,
+                    comment: None,
+                },
+            );
+        }
+
+        restored
+    }
+
+    async fn fetch_torrent_file(client: &HttpClient, file_url: &url::Url) -> Result<Vec<u8>> {
+        log::debug!("Fetching torrent file from {}", file_url);
+        let response = client.get(file_url).await.map_err(|err| {
+            log::error!("Failed to fetch torrent file from {}", &file_url);
+            err
+        })?;
+        Ok(response.bytes().await?.to_vec())
+    }
+
+    /// Send stats for the torrent every second until it finishes or is paused.
+    /// Replaces any previous receiver, which stops the task sending to it.
+    fn watch_stats(&mut self, source_id: &str, handle: Arc<ManagedTorrent>) {
+        let (tx, rx) = watch::channel(self.stats_for(source_id, handle.clone()));
+        self.receivers.insert(source_id.to_owned(), rx);
+
+        tokio::spawn({
+            let id = source_id.to_owned();
+            let limits = self.limits.clone();
+            let uploads = self.seeding.uploads.clone();
+            async move {
+                let stats = |handle: &Arc<ManagedTorrent>| {
+                    let limits = limits.read().unwrap().effective(&id);
+                    let upload = uploads.read().unwrap().get(&id).copied();
+                    Self::to_stats(
+                        id.to_owned(),
+                        handle.clone(),
+                        limits,
+                        upload.unwrap_or_default(),
+                    )
+                };
+
+                while !handle.stats().finished && !handle.is_paused() {
+                    info!("{}", handle.stats());
+                    tx.send(stats(&handle))?;
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                }
+                tx.send(stats(&handle))?;
+                info!("{}", handle.stats());
+                Ok(())
+            }
+        });
+    }
+
+    fn stats_for(&self, source_id: &str, handle: Arc<ManagedTorrent>) -> TorrentStats {
+        let limits = self.limits.read().unwrap().effective(source_id);
+        let upload = self.seeding.upload_record(source_id);
+        Self::to_stats(source_id.to_owned(), handle, limits, upload)
+    }
+
+    /// Limits for `source_id` to use the next time it's added, unlimited ones remove the override.
+    /// Returns the limits it had before.
+    fn store_torrent_limits(
+        &self,
+        source_id: &str,
+        limits: BandwidthLimits,
+    ) -> Option<BandwidthLimits> {
+        let mut rate_limits = self.limits.write().unwrap();
+        if limits.is_unlimited() {
+            rate_limits.torrents.remove(source_id)
+        } else {
+            rate_limits.torrents.insert(source_id.to_owned(), limits)
+        }
     }
 
-    fn to_stats(id: String, handle: Arc<ManagedTorrent>) -> TorrentStats {
+    /// Add `broken` back to the session as `add_torrent`, paused so nothing is downloaded.
+    /// Orphaned torrents have no known folder and go to the session's default one.
+    async fn restore_broken(
+        &mut self,
+        mut broken: BrokenTorrent,
+        add_torrent: AddTorrent<'_>,
+        only_files: Option<Vec<usize>>,
+    ) -> Result<()> {
+        let options = AddTorrentOptions {
+            overwrite: true,
+            paused: true,
+            only_files,
+            output_folder: broken
+                .output_dir
+                .as_ref()
+                .and_then(|output_dir| output_dir.to_str())
+                .map(str::to_owned),
+            ..Default::default()
+        };
+        let handle = self
+            .session
+            .add_torrent(add_torrent, Some(options))
+            .await?
+            .into_handle()
+            .context(format!("Torrent {} was not added", broken.torrent_id))?;
+
+        broken.torrent_id = handle.id();
+        self.broken.insert(handle.id(), broken);
+        Ok(())
+    }
+
+    /// Add a torrent to the session under `source_id`, with any limits set for it
+    async fn add(
+        &mut self,
+        source_id: &str,
+        add_torrent: AddTorrent<'_>,
+        output_dir: &Path,
+        only_files: Option<Vec<usize>>,
+        paused: bool,
+    ) -> Result<()> {
+        let limits = self
+            .limits
+            .read()
+            .unwrap()
+            .torrents
+            .get(source_id)
+            .copied()
+            .unwrap_or_default();
+
+        let options = AddTorrentOptions {
+            overwrite: true,
+            paused,
+            only_files,
+            output_folder: Some(
+                output_dir
+                    .to_str()
+                    .context(format!("Invalid output dir {}", output_dir.display()))?
+                    .to_owned(),
+            ),
+            ratelimits: LimitsConfig {
+                upload_bps: limits.upload_bps.and_then(NonZeroU32::new),
+                download_bps: limits.download_bps.and_then(NonZeroU32::new),
+            },
+            ..Default::default()
+        };
+
+        let handle = self
+            .session
+            .add_torrent(add_torrent, Some(options))
+            .await?
+            .into_handle()
+            .context(format!("Torrent for {} was not added", source_id))?;
+
+        self.id_translation
+            .insert(handle.id(), source_id.to_owned());
+        self.output_dirs
+            .insert(source_id.to_owned(), output_dir.to_path_buf());
+
+        if !paused {
+            self.watch_stats(source_id, handle.clone());
+        }
+        self.handles.insert(source_id.to_owned(), handle);
+
+        Ok(())
+    }
+
+    fn to_stats(
+        id: String,
+        handle: Arc<ManagedTorrent>,
+        limits: BandwidthLimits,
+        upload: UploadRecord,
+    ) -> TorrentStats {
         let stats = handle.stats();
+        let uploaded_bytes = upload.total(stats.uploaded_bytes);
         TorrentStats {
             id,
             name: handle.name().unwrap_or("".to_owned()),
             state: stats.state.to_string(),
             progress_bytes: stats.progress_bytes,
-            uploaded_bytes: stats.uploaded_bytes,
+            uploaded_bytes,
             total_bytes: stats.total_bytes,
             finished: stats.finished,
+            ratio: seeding_ratio(uploaded_bytes, stats.total_bytes),
             upload_speed: stats.live.as_ref().map(|l| l.upload_speed.mbps),
             download_speed: stats.live.as_ref().map(|l| l.download_speed.mbps),
             remaining_time: stats
@@ -142,6 +550,9 @@ impl RqbitService {
                 .map(|l| l.time_remaining.as_ref())
                 .flatten()
                 .map(|d| d.to_string()),
+            upload_limit_bps: limits.upload_bps,
+            download_limit_bps: limits.download_bps,
+            queue_position: None,
         }
     }
 }
@@ -149,83 +560,153 @@ impl RqbitService {
 #[cfg_attr(test, automock)]
 #[async_trait]
 impl TorrentService for RqbitService {
+    fn resolve_torrent(
+        &self,
+        source_id: &str,
+        source: &TorrentSource,
+    ) -> BoxFuture<'static, Result<ResolvedTorrent>> {
+        let session = self.session.clone();
+        let client = self.client.clone();
+        let magnet_timeout = self.magnet_timeout;
+        let source_id = source_id.to_owned();
+        let source = source.clone();
+
+        Box::pin(async move {
+            let torrent_file = match &source {
+                TorrentSource::Magnet(uri) => {
+                    return Self::resolve_magnet(&session, &source_id, uri, magnet_timeout).await
+                }
+                TorrentSource::TorrentFile(file_url) => file_url,
+                TorrentSource::MagnetWithFallback {
+                    magnet,
+                    torrent_file,
+                } => {
+                    match Self::resolve_magnet(&session, &source_id, magnet, magnet_timeout).await
+                    {
+                        Result::Ok(torrent) => return Ok(torrent),
+                        Err(err) => log::warn!(
+                            "Failed to resolve magnet for {} ({:#}), falling back to the torrent file",
+                            source_id,
+                            err
+                        ),
+                    }
+                    torrent_file
+                }
+            };
+
+            Ok(ResolvedTorrent {
+                torrent_bytes: Self::fetch_torrent_file(&client, torrent_file).await?,
+            })
+        })
+    }
+
     async fn download_torrent(
         &mut self,
         source_id: &str,
-        file_url: &url::Url,
-        filename: &str,
+        torrent: &ResolvedTorrent,
         output_dir: &Path,
+        only_files: Option<Vec<usize>>,
     ) -> Result<()> {
         if !output_dir.exists() {
             create_dir(output_dir).await?;
         }
 
-        let torrent_file_location = self
-            .download_torrent_file(file_url, filename, output_dir)
-            .await?;
-
-        let mut options = AddTorrentOptions::default();
-        options.overwrite = true;
-        options.output_folder = Some(
-            output_dir
-                .to_str()
-                .expect("output dir to be valid")
-                .to_owned(),
-        );
-
-        let handle = self
-            .session
-            .add_torrent(
-                AddTorrent::from_local_filename(torrent_file_location.to_str().unwrap())?,
-                Some(options),
-            )
-            .await?
-            .into_handle()
-            .unwrap();
-
-        self.id_translation
-            .insert(handle.id(), source_id.to_owned());
+        let add_torrent = AddTorrent::from_bytes(torrent.torrent_bytes.clone());
+        self.add(source_id, add_torrent, output_dir, only_files, false)
+            .await
+    }
 
-        let (tx, rx) = watch::channel(Self::to_stats(source_id.to_owned(), handle.clone()));
-        self.receivers.insert(source_id.to_owned(), rx);
+    async fn list_files(&self, source: &TorrentSource) -> Result<Vec<TorrentFile>> {
+        match source {
+            // the .torrent file lists the files without having to find peers first
+            TorrentSource::TorrentFile(file_url)
+            | TorrentSource::MagnetWithFallback {
+                torrent_file: file_url,
+                ..
+            } => {
+                let bytes = Self::fetch_torrent_file(&self.client, file_url).await?;
+                list_torrent_file(&bytes)
+            }
+            TorrentSource::Magnet(uri) => {
+                let options = AddTorrentOptions {
+                    list_only: true,
+                    ..Default::default()
+                };
 
-        tokio::spawn({
-            let h = handle.clone();
-            let id = source_id.to_owned();
-            async move {
-                while !h.stats().finished && !h.is_paused() {
-                    let stats = h.stats();
-                    info!("{}", stats);
-                    tx.send(Self::to_stats(id.to_owned(), h.clone()))?;
-                    tokio::time::sleep(Duration::from_secs(1)).await;
+                match self
+                    .session
+                    .add_torrent(AddTorrent::from_url(uri.to_owned()), Some(options))
+                    .await?
+                {
+                    AddTorrentResponse::ListOnly(response) => to_files(&response.info, None),
+                    _ => bail!("Expected only a list of files for {}", uri),
                 }
-                tx.send(Self::to_stats(id.to_owned(), h.clone()))?;
-                info!("{}", h.stats());
-                Ok(())
             }
-        });
+        }
+    }
 
-        self.handles.insert(source_id.to_owned(), handle);
+    fn get_files(&self, source_id: &str) -> Result<Vec<TorrentFile>> {
+        let handle = self
+            .handles
+            .get(source_id)
+            .context(format!("No download with id {}", source_id))?;
 
-        Ok(())
+        let only_files = handle.only_files();
+        handle.with_metadata(|metadata| to_files(&metadata.info, only_files.as_deref()))?
     }
 
-    async fn wait_until_finished(&mut self, source_id: &str) -> Result<()> {
+    async fn update_files(&mut self, source_id: &str, only_files: Vec<usize>) -> Result<()> {
         let handle = self
             .handles
             .get(source_id)
-            .context(format!("No download with id {}", source_id))?;
+            .context(format!("No download with id {}", source_id))?
+            .clone();
 
-        handle.wait_until_completed().await?;
-        log::info!("Download for {} is complete!", source_id);
+        log::info!("Downloading files {:?} of {}", only_files, source_id);
+        self.session
+            .update_only_files(&handle, &only_files.into_iter().collect::<HashSet<usize>>())
+            .await?;
 
-        self.id_translation.remove(&handle.id());
-        self.handles.remove(source_id);
-        self.receivers.remove(source_id);
+        // a finished torrent starts again when files are added
+        if !handle.is_paused() {
+            self.watch_stats(source_id, handle);
+        }
 
         Ok(())
     }
 
+    fn wait_until_finished(&self, source_id: &str) -> Result<BoxFuture<'static, Result<()>>> {
+        let info_hash = self
+            .handles
+            .get(source_id)
+            .context(format!("No download with id {}", source_id))?
+            .info_hash();
+        let session = self.session.clone();
+        let source_id = source_id.to_owned();
+
+        Ok(Box::pin(async move {
+            loop {
+                // looked up each time since changing the limits adds the torrent again
+                let handle = session
+                    .get(TorrentIdOrHash::Hash(info_hash))
+                    .context(format!("{} was removed before it finished", source_id))?;
+                if handle.stats().finished {
+                    break;
+                }
+                // pausing leaves this waiting forever, so check back every so often
+                if let Result::Ok(result) =
+                    tokio::time::timeout(FINISHED_CHECK_INTERVAL, handle.wait_until_completed())
+                        .await
+                {
+                    result?;
+                }
+            }
+
+            log::info!("Download for {} is complete!", source_id);
+            Ok(())
+        }))
+    }
+
     fn get_stats_receiver(&self, source_id: &str) -> Option<Receiver<TorrentStats>> {
         self.receivers.get(source_id).cloned()
     }
@@ -233,14 +714,10 @@ impl TorrentService for RqbitService {
     fn list_torrents(&self) -> Vec<TorrentStats> {
         self.session.with_torrents(|torrents| {
             torrents
-                .map(|(id, torrent)| {
-                    Self::to_stats(
-                        self.id_translation
-                            .get(&id)
-                            .expect("Torrent to have a source id")
-                            .to_owned(),
-                        torrent.clone(),
-                    )
+                // broken torrents have no source id until they are repaired
+                .filter_map(|(id, torrent)| {
+                    let source_id = self.id_translation.get(&id)?;
+                    Some(self.stats_for(source_id, torrent.clone()))
                 })
                 .collect::<Vec<TorrentStats>>()
         })
@@ -256,26 +733,10 @@ impl TorrentService for RqbitService {
             self.session.unpause(handle).await?;
 
             if self.receivers.get(source_id).is_none() {
-                let (tx, rx) = watch::channel(Self::to_stats(source_id.to_owned(), handle.clone()));
-                self.receivers.insert(source_id.to_owned(), rx);
-
-                tokio::spawn({
-                    let h = handle.clone();
-                    let id = source_id.to_owned();
-                    async move {
-                        while !h.stats().finished && !h.is_paused() {
-                            let stats = h.stats();
-                            info!("{}", stats);
-                            tx.send(Self::to_stats(id.to_owned(), h.clone()))?;
-                            tokio::time::sleep(Duration::from_secs(1)).await;
-                        }
-                        tx.send(Self::to_stats(id.to_owned(), h.clone()))?;
-                        info!("{}", h.stats());
-                        Ok(())
-                    }
-                });
+                self.watch_stats(source_id, handle.clone());
             }
         } else {
+            self.seeding.checkpoint(source_id, handle);
             self.session.pause(handle).await?;
             self.receivers.remove(source_id);
         }
@@ -295,8 +756,553 @@ impl TorrentService for RqbitService {
             .await?;
 
         self.id_translation.remove(&handle.id());
+        self.output_dirs.remove(source_id);
+        self.receivers.remove(source_id);
+        self.seeding.forget(source_id);
+
+        Ok(())
+    }
+
+    fn set_global_limits(&mut self, limits: BandwidthLimits) {
+        log::info!("Setting global limits to {:?}", limits);
+        self.session
+            .ratelimits
+            .set_upload_bps(limits.upload_bps.and_then(NonZeroU32::new));
+        self.session
+            .ratelimits
+            .set_download_bps(limits.download_bps.and_then(NonZeroU32::new));
+        self.limits.write().unwrap().global = limits;
+    }
+
+    async fn set_torrent_limits(&mut self, source_id: &str, limits: BandwidthLimits) -> Result<()> {
+        log::info!("Setting limits for {} to {:?}", source_id, limits);
+        let previous = self.store_torrent_limits(source_id, limits);
+
+        let Some(handle) = self.handles.get(source_id).cloned() else {
+            // applied once the torrent is added
+            return Ok(());
+        };
+        if previous.unwrap_or_default() == limits {
+            return Ok(());
+        }
+
+        // librqbit only reads per torrent limits when a torrent is added, so it's added again.
+        // Files already on disk are checked rather than downloaded again.
+        let torrent_bytes = handle.with_metadata(|metadata| metadata.torrent_bytes.clone())?;
+        let output_dir = self
+            .output_dirs
+            .get(source_id)
+            .context(format!("Missing output dir for {}", source_id))?
+            .clone();
+        let only_files = handle.only_files();
+        let paused = handle.is_paused();
+
+        self.seeding.checkpoint(source_id, &handle);
+        self.session
+            .delete(TorrentIdOrHash::Id(handle.id()), false)
+            .await?;
+        self.id_translation.remove(&handle.id());
         self.receivers.remove(source_id);
 
+        let result = self
+            .add(
+                source_id,
+                AddTorrent::from_bytes(torrent_bytes.clone()),
+                &output_dir,
+                only_files.clone(),
+                paused,
+            )
+            .await;
+        let Err(err) = result else {
+            return Ok(());
+        };
+
+        log::error!(
+            "Failed to add {} with new limits, restoring the previous ones: {}",
+            source_id,
+            err
+        );
+        self.store_torrent_limits(source_id, previous.unwrap_or_default());
+        if let Err(restore_err) = self
+            .add(
+                source_id,
+                AddTorrent::from_bytes(torrent_bytes),
+                &output_dir,
+                only_files,
+                paused,
+            )
+            .await
+        {
+            self.handles.remove(source_id);
+            return Err(err.context(format!(
+                "Failed to restore {} as well: {}",
+                source_id, restore_err
+            )));
+        }
+        Err(err)
+    }
+
+    fn set_global_seeding_policy(&mut self, policy: SeedingPolicy) {
+        log::info!("Setting global seeding policy to {:?}", policy);
+        self.seeding.global = policy;
+    }
+
+    fn set_torrent_seeding_policy(&mut self, source_id: &str, policy: Option<SeedingPolicy>) {
+        log::info!("Setting seeding policy for {} to {:?}", source_id, policy);
+        match policy {
+            Some(policy) => self.seeding.torrents.insert(source_id.to_owned(), policy),
+            None => self.seeding.torrents.remove(source_id),
+        };
+    }
+
+    async fn enforce_seeding_policies(&mut self) -> Vec<String> {
+        let now = Utc::now();
+        let mut to_stop = vec![];
+
+        {
+            let mut uploads = self.seeding.uploads.write().unwrap();
+            for (source_id, handle) in &self.handles {
+                let stats = handle.stats();
+                let record = uploads.entry(source_id.to_owned()).or_default();
+                record.update(stats.uploaded_bytes);
+                if !stats.finished || handle.is_paused() || self.seeding.stopped.contains(source_id)
+                {
+                    continue;
+                }
+
+                let seeding_since = *record.seeding_since.get_or_insert(now);
+                let ratio = seeding_ratio(record.total(stats.uploaded_bytes), stats.total_bytes);
+                let policy = self.seeding.effective(source_id);
+                let seeding_for = (now - seeding_since).to_std().unwrap_or_default();
+                if !policy.should_stop(ratio, seeding_for) {
+                    continue;
+                }
+
+                log::info!(
+                    "Stopped seeding {} at ratio {:.2} with policy {:?}",
+                    source_id,
+                    ratio,
+                    policy
+                );
+                to_stop.push((source_id.to_owned(), handle.clone()));
+            }
+        }
+
+        let mut stopped = vec![];
+        for (source_id, handle) in to_stop {
+            self.seeding.checkpoint(&source_id, &handle);
+            match self.session.pause(&handle).await {
+                Result::Ok(()) => stopped.push(source_id),
+                Err(err) => log::error!("Failed to stop seeding {}: {}", source_id, err),
+            }
+        }
+
+        for source_id in &stopped {
+            self.receivers.remove(source_id);
+            self.seeding.stopped.insert(source_id.to_owned());
+        }
+
+        let uploads = self.seeding.uploads.read().unwrap().clone();
+        if let Err(err) = write_upload_records(&self.seeding.uploads_path, &uploads).await {
+            log::error!("Failed to save uploads: {}", err);
+        }
+        stopped
+    }
+
+    fn broken_torrents(&self) -> Vec<BrokenTorrent> {
+        let mut broken: Vec<BrokenTorrent> = self.broken.values().cloned().collect();
+        broken.sort_by_key(|torrent| torrent.torrent_id);
+        broken
+    }
+
+    async fn repair_torrent(
+        &mut self,
+        torrent_id: usize,
+        source_id: &str,
+        output_dir: &Path,
+    ) -> Result<()> {
+        let Some(broken) = self.broken.get(&torrent_id).cloned() else {
+            bail!("Torrent {} doesn't need repairing", torrent_id);
+        };
+        if self.handles.contains_key(source_id) {
+            bail!("{} already has a torrent", source_id);
+        }
+        let handle = self
+            .session
+            .get(TorrentIdOrHash::Id(torrent_id))
+            .context(format!("No torrent with id {}", torrent_id))?;
+        let torrent_bytes = handle
+            .with_metadata(|metadata| metadata.torrent_bytes.clone())
+            .context(format!(
+                "Torrent {} has no metadata to add it again with",
+                torrent_id
+            ))?;
+
+        log::info!(
+            "Repairing torrent {} as {} in {}",
+            torrent_id,
+            source_id,
+            output_dir.display()
+        );
+        if !output_dir.exists() {
+            create_dir_all(output_dir).await?;
+        }
+        let only_files = handle.only_files();
+        let paused = handle.is_paused();
+        self.session
+            .delete(TorrentIdOrHash::Id(torrent_id), false)
+            .await?;
+        self.broken.remove(&torrent_id);
+
+        let result = self
+            .add(
+                source_id,
+                AddTorrent::from_bytes(torrent_bytes.clone()),
+                output_dir,
+                only_files.clone(),
+                paused,
+            )
+            .await;
+        let Err(err) = result else {
+            return Ok(());
+        };
+
+        log::error!(
+            "Failed to repair torrent {}, keeping it as broken: {}",
+            torrent_id,
+            err
+        );
+        if let Err(restore_err) = self
+            .restore_broken(broken, AddTorrent::from_bytes(torrent_bytes), only_files)
+            .await
+        {
+            return Err(err.context(format!(
+                "Failed to restore torrent {} as well: {}",
+                torrent_id, restore_err
+            )));
+        }
+        Err(err)
+    }
+
+    async fn forget_torrent(&mut self, torrent_id: usize) -> Result<()> {
+        if !self.broken.contains_key(&torrent_id) {
+            bail!("Torrent {} can't be forgotten, it isn't broken", torrent_id);
+        }
+
+        log::info!("Forgetting torrent {}", torrent_id);
+        self.session
+            .delete(TorrentIdOrHash::Id(torrent_id), false)
+            .await?;
+        self.broken.remove(&torrent_id);
         Ok(())
     }
 }
+
+/// Files listed in the bytes of a .torrent file
+fn list_torrent_file(bytes: &[u8]) -> Result<Vec<TorrentFile>> {
+    let torrent = torrent_from_bytes::<ByteBuf>(bytes).context("Invalid torrent file")?;
+    to_files(&torrent.info, None)
+}
+
+/// Comment of the .torrent file `handle` was added from, torrents resolved from a magnet have none
+fn torrent_comment(handle: &ManagedTorrent) -> Option<String> {
+    handle
+        .with_metadata(|metadata| {
+            let torrent = torrent_from_bytes::<ByteBuf>(&metadata.torrent_bytes).ok()?;
+            torrent
+                .comment
+                .map(|comment| String::from_utf8_lossy(&comment).into_owned())
+        })
+        .ok()
+        .flatten()
+}
+
+fn to_files<B: AsRef<[u8]>>(
+    info: &TorrentMetaV1Info<B>,
+    only_files: Option<&[usize]>,
+) -> Result<Vec<TorrentFile>> {
+    info.iter_file_details()?
+        .enumerate()
+        // padding files only exist to align pieces, they are never written
+        .filter(|(_, details)| !details.attrs().padding)
+        .map(|(index, details)| {
+            Ok(TorrentFile {
+                index,
+                path: details.filename.to_string()?,
+                size: details.len,
+                selected: only_files.map_or(true, |only_files| only_files.contains(&index)),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use librqbit::{Session, SessionOptions};
+    use tempdir::TempDir;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    use super::*;
+
+    /// Serves `body` as the response to a single http request
+    async fn serve_once(body: Vec<u8>) -> url::Url {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = url::Url::parse(&format!("http://{}/", listener.local_addr().unwrap())).unwrap();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buf = [0; 1024];
+            let _ = stream.read(&mut buf).await;
+            let header = format!(
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                body.len()
+            );
+            stream.write_all(header.as_bytes()).await.unwrap();
+            stream.write_all(&body).await.unwrap();
+        });
+        url
+    }
+
+    #[test]
+    fn test_list_torrent_file() {
+        let torrent = b"d4:infod5:filesl\
+            d6:lengthi3e4:pathl7:v01.cbzee\
+            d6:lengthi5e4:pathl6:extras7:v02.cbzee\
+            e4:name5:Batch12:piece lengthi16384e6:pieces20:aaaaaaaaaaaaaaaaaaaaee";
+
+        let files = list_torrent_file(torrent).unwrap();
+        assert_eq!(
+            files,
+            vec![
+                TorrentFile {
+                    index: 0,
+                    path: "v01.cbz".to_owned(),
+                    size: 3,
+                    selected: true,
+                },
+                TorrentFile {
+                    index: 1,
+                    path: PathBuf::from("extras")
+                        .join("v02.cbz")
+                        .to_string_lossy()
+                        .to_string(),
+                    size: 5,
+                    selected: true,
+                },
+            ]
+        );
+
+        assert!(list_torrent_file(b"not a torrent").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_restore_session() {
+        let dir = tempdir::TempDir::new("session").unwrap();
+        let restored_dir = dir.path().join("Frieren");
+        let unreadable_dir = dir.path().join("Dandadan");
+        std::fs::create_dir(&restored_dir).unwrap();
+        std::fs::create_dir(&unreadable_dir).unwrap();
+        std::fs::write(unreadable_dir.join(".meta"), "not json").unwrap();
+        let source = crate::source::SourceMeta::local();
+        Metafile::new(source.clone(), None)
+            .write(&restored_dir)
+            .await
+            .unwrap();
+
+        let session_store = dir.path().join("session.json");
+        let torrent = |output_dir: &Path| serde_json::json!({ "output_folder": output_dir });
+        std::fs::write(
+            &session_store,
+            serde_json::json!({ "torrents": {
+                "1": torrent(&restored_dir),
+                "2": torrent(&unreadable_dir),
+                "3": torrent(&dir.path().join("Deleted")),
+            }})
+            .to_string(),
+        )
+        .unwrap();
+
+        let restored = RqbitService::restore_session(&session_store).await;
+        assert_eq!(
+            restored.id_translation,
+            HashMap::from([(1, source.id.clone())])
+        );
+        assert_eq!(restored.output_dirs[&source.id], restored_dir);
+        assert!(matches!(
+            restored.broken[&2].problem,
+            RestoreProblem::UnreadableMetafile { .. }
+        ));
+        assert_eq!(restored.broken[&3].problem, RestoreProblem::MissingFolder);
+
+        // a corrupt session store restores nothing rather than failing
+        std::fs::write(&session_store, "{").unwrap();
+        let restored = RqbitService::restore_session(&session_store).await;
+        assert!(restored.id_translation.is_empty() && restored.broken.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_upload_records() {
+        let mut record = UploadRecord::default();
+        record.update(100);
+        assert_eq!(record.total(150), 150);
+        // the live counter started over without a checkpoint
+        assert_eq!(record.total(20), 120);
+        record.update(20);
+        assert_eq!(record.total(20), 120);
+
+        // pausing checkpoints first, librqbit reports 0 for paused torrents
+        record.checkpoint(50);
+        assert_eq!(record.total(0), 150);
+
+        record.update(30);
+        record.seeding_since = Some(Utc::now());
+        let dir = TempDir::new("uploads").unwrap();
+        let path = dir.path().join(UPLOADS_FILE);
+        let records = HashMap::from([("1990813".to_owned(), record)]);
+        write_upload_records(&path, &records).await.unwrap();
+
+        // after a restart the live counter starts from 0 again
+        let restored = read_upload_records(&path).await;
+        assert_eq!(restored["1990813"].total(0), 180);
+        assert_eq!(restored["1990813"].seeding_since, record.seeding_since);
+
+        std::fs::write(&path, "{").unwrap();
+        assert!(read_upload_records(&path).await.is_empty());
+    }
+
+    /// A service that can't reach any peers
+    async fn offline_service(dir: &TempDir) -> RqbitService {
+        let session = Session::new_with_opts(
+            dir.path().to_path_buf(),
+            SessionOptions {
+                disable_dht: true,
+                disable_dht_persistence: true,
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+        RqbitService::new(
+            session,
+            HttpClient::new(reqwest::Client::new()),
+            &dir.path().join("session.json"),
+        )
+        .await
+    }
+
+    fn recorded_torrent() -> Vec<u8> {
+        std::fs::read(
+            Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/nyaa/1990813.torrent"),
+        )
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_saved_torrent_limits() {
+        let dir = TempDir::new("rqbit").unwrap();
+        let mut service = offline_service(&dir).await;
+        let torrent = ResolvedTorrent {
+            torrent_bytes: recorded_torrent(),
+        };
+        service
+            .download_torrent("1990813", &torrent, &dir.path().join("Ushi"), None)
+            .await
+            .unwrap();
+        let torrent_id = |service: &RqbitService| service.handles["1990813"].id();
+        let restored_id = torrent_id(&service);
+
+        // the torrent is added again to pick up its saved limits
+        let limits = BandwidthLimits {
+            upload_bps: Some(64 * 1024),
+            download_bps: None,
+        };
+        let mut service = service
+            .with_torrent_limits(HashMap::from([("1990813".to_owned(), limits)]))
+            .await;
+        let limited_id = torrent_id(&service);
+        assert_ne!(limited_id, restored_id);
+
+        // and only once, it already has them
+        service.set_torrent_limits("1990813", limits).await.unwrap();
+        assert_eq!(torrent_id(&service), limited_id);
+        service.remove_torrent("1990813").await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_orphaned_torrent_keeps_comment() {
+        let dir = TempDir::new("rqbit").unwrap();
+        let mut service = offline_service(&dir).await;
+        // keys of a torrent file are sorted, so the comment goes between announce and info
+        let recorded = recorded_torrent();
+        let info_start = recorded.windows(7).position(|w| w == b"4:infod").unwrap();
+        let comment = "https://nyaa.si/view/1990813";
+        let torrent_bytes = [
+            &recorded[..info_start],
+            format!("7:comment{}:{}", comment.len(), comment).as_bytes(),
+            &recorded[info_start..],
+        ]
+        .concat();
+        service
+            .download_torrent(
+                "1990813",
+                &ResolvedTorrent { torrent_bytes },
+                &dir.path().join("Ushi"),
+                None,
+            )
+            .await
+            .unwrap();
+        let torrent_id = service.handles["1990813"].id();
+
+        // the session store lost the torrent
+        service.id_translation.clear();
+        service.handles.clear();
+        service.restore_handles();
+
+        let broken = &service.broken_torrents()[0];
+        assert_eq!(broken.problem, RestoreProblem::Orphaned);
+        assert!(broken.name.starts_with("My Quiet Blacksmith Life"));
+        assert_eq!(broken.comment.as_deref(), Some(comment));
+        service.forget_torrent(torrent_id).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_hung_magnet_falls_back_to_torrent_file() {
+        let dir = TempDir::new("rqbit").unwrap();
+        let mut service = offline_service(&dir)
+            .await
+            .with_magnet_timeout(Duration::from_millis(500));
+
+        // a tracker that takes the connection and never answers, so the magnet never resolves
+        let tracker = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let magnet = format!(
+            "magnet:?xt=urn:btih:{}&tr=http://{}/announce",
+            "ab".repeat(20),
+            tracker.local_addr().unwrap()
+        );
+        let torrent_file = recorded_torrent();
+        let source = TorrentSource::MagnetWithFallback {
+            magnet,
+            torrent_file: serve_once(torrent_file.clone()).await,
+        };
+
+        let torrent = tokio::time::timeout(
+            Duration::from_secs(10),
+            service.resolve_torrent("1990813", &source),
+        )
+        .await
+        .expect("the magnet timeout to stop waiting on the tracker")
+        .unwrap();
+        assert_eq!(torrent.torrent_bytes, torrent_file);
+
+        service
+            .download_torrent("1990813", &torrent, &dir.path().join("Ushi"), None)
+            .await
+            .unwrap();
+
+        assert!(service.get_stats_receiver("1990813").is_some());
+        assert_eq!(service.list_torrents().len(), 1);
+        service.remove_torrent("1990813").await.unwrap();
+    }
+}
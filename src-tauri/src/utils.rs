@@ -1,5 +1,6 @@
 use std::path::Path;
 
+use crate::http::HttpClient;
 use anyhow::Result;
 use flate2::read::GzDecoder;
 use futures::StreamExt;
@@ -9,15 +10,14 @@ use tokio::{
 };
 
 pub async fn download_file_from_url(
-    client: &reqwest::Client,
+    client: &HttpClient,
     url: &url::Url,
     filename: &str,
     output_dir: &Path,
 ) -> Result<()> {
     let output_path = output_dir.join(&filename);
 
-    let request = client.get(url.as_str());
-    let response = request.send().await?.error_for_status()?;
+    let response = client.get(url).await?;
 
     let mut file = File::create(output_path).await?;
     let mut stream = response.bytes_stream();
@@ -0,0 +1,117 @@
+use std::{
+    fs,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+use url::Url;
+
+/// Local stand-in for nyaa that serves the pages in `tests/fixtures/nyaa`.
+/// They are cut down to the markup the scraper reads and only use classes and
+/// attributes the real site emits, replace them with saved pages when the layout changes.
+///
+/// Routes mirror the real site:
+/// - `/?...&page=rss` serves `rss.xml`
+/// - `/?...&q={text}` serves `search_{text}.html` for searches with text, like `search_empty.html`
+/// - `/?...&p={page}` serves `search_{page}.html`
+/// - `/user/{name}?...&p={page}` serves `search_{page}.html` as well, the listing is the same
+/// - `/view/{id}` serves `view_{id}.html`
+/// - `/download/{id}.torrent` serves `{id}.torrent`
+///
+/// Anything without a matching fixture gets a 404.
+pub struct NyaaStandIn {
+    pub url: Url,
+    requests: Arc<Mutex<Vec<Url>>>,
+}
+
+impl NyaaStandIn {
+    pub async fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = Url::parse(&format!("http://{}/", listener.local_addr().unwrap())).unwrap();
+        let requests = Arc::new(Mutex::new(vec![]));
+
+        let base_url = url.clone();
+        let recorded = requests.clone();
+        tokio::spawn(async move {
+            loop {
+                let Ok((stream, _)) = listener.accept().await else {
+                    break;
+                };
+                tokio::spawn(handle(stream, base_url.clone(), recorded.clone()));
+            }
+        });
+
+        NyaaStandIn { url, requests }
+    }
+
+    /// Every url requested so far, in order
+    pub fn requests(&self) -> Vec<Url> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+pub fn fixture_path(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures/nyaa")
+        .join(name)
+}
+
+fn fixture_name(url: &Url) -> Option<String> {
+    let path = url.path();
+    if path == "/" || path.starts_with("/user/") {
+        let param = |name: &str| {
+            url.query_pairs()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value.into_owned())
+        };
+
+        return match (param("page").as_deref(), param("q")) {
+            (Some("rss"), _) => Some("rss.xml".to_owned()),
+            (_, Some(text)) if !text.is_empty() => Some(format!("search_{}.html", text)),
+            _ => Some(format!(
+                "search_{}.html",
+                param("p").unwrap_or("1".to_owned())
+            )),
+        };
+    }
+
+    if let Some(id) = path.strip_prefix("/view/") {
+        return Some(format!("view_{}.html", id));
+    }
+
+    path.strip_prefix("/download/").map(|file| file.to_owned())
+}
+
+async fn handle(mut stream: TcpStream, base_url: Url, requests: Arc<Mutex<Vec<Url>>>) {
+    // requests are all bodiless GETs, so reading up to the end of the headers is enough
+    let mut request = vec![];
+    let mut buf = [0; 1024];
+    while !request.ends_with(b"\r\n\r\n") {
+        match stream.read(&mut buf).await {
+            Ok(0) | Err(_) => return,
+            Ok(n) => request.extend_from_slice(&buf[..n]),
+        }
+    }
+
+    let request = String::from_utf8_lossy(&request);
+    let target = request.split_whitespace().nth(1).unwrap_or("/");
+    let url = base_url.join(target).unwrap();
+    requests.lock().unwrap().push(url.clone());
+
+    let (status, body) = match fixture_name(&url).map(|name| fs::read(fixture_path(&name))) {
+        Some(Ok(body)) => ("200 OK", body),
+        _ => ("404 Not Found", b"Not Found".to_vec()),
+    };
+
+    let headers = format!(
+        "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status,
+        body.len()
+    );
+    let _ = stream.write_all(headers.as_bytes()).await;
+    let _ = stream.write_all(&body).await;
+}
@@ -1,5 +1,6 @@
 use std::{env, fs::read_dir, io, vec};
 
+use app_lib::http::HttpClient;
 use app_lib::metadata::{mangabaka::Mangabaka, MetadataProvider};
 use dotenv::dotenv;
 use sqlx::sqlite::SqlitePoolOptions;
@@ -8,7 +9,7 @@ use tempdir::TempDir;
 #[tokio::test(flavor = "multi_thread")]
 #[ignore]
 async fn test_db_setup() {
-    let client = reqwest::Client::new();
+    let client = HttpClient::new(reqwest::Client::new());
     let dir = TempDir::new("test").unwrap();
     Mangabaka::setup(&client, dir.path()).await.unwrap();
     let files = read_dir(dir.path())
@@ -1,30 +1,55 @@
 use anyhow::Result;
-use app_lib::source::PaginationInfo;
+use app_lib::http::HttpClient;
+use app_lib::metafile::Metafile;
+use app_lib::source::cache::ResponseCache;
+use app_lib::source::nyaa::category::{LiteratureSubCategory, NyaaCategory};
+use app_lib::source::nyaa::NyaaBackend;
 use app_lib::source::{nyaa::Nyaa, Source};
+use app_lib::source::{
+    Category, PaginationInfo, ReleaseStatus, SearchQuery, SizeUnit, SourceMeta, Sources,
+};
+use app_lib::torrent::queue::DownloadQueue;
 use app_lib::torrent::rqbit_service::RqbitService;
-use app_lib::torrent::TorrentService;
+use app_lib::torrent::{ResolvedTorrent, TorrentFile, TorrentService, TorrentSource};
 use async_trait::async_trait;
-use librqbit::Session;
+use librqbit::{Session, SessionOptions};
 use std::sync::Arc;
+use std::time::Duration;
 use std::{fs::read_dir, io};
 use tempdir::TempDir;
 use tokio::sync::Mutex;
 
 use mockall::mock;
 
+mod common;
+
+use common::{fixture_path, NyaaStandIn};
+
+const INFO_HASH: &str = "05152553e73e4bf7cdaa4ab3dffb6bb9d7bf7593";
+/// Title of the upload in `view_1990813.html`, which the recorded torrent is named after
+const TITLE: &str = "My Quiet Blacksmith Life in Another World v05 (2025) (Digital) (Ushi)";
+
 mock! {
     pub TorrentService {}
     #[async_trait]
     impl TorrentService for TorrentService {
+    fn resolve_torrent(&self, id: &str, source: &TorrentSource) -> futures::future::BoxFuture<'static, Result<ResolvedTorrent>>;
+
     async fn download_torrent(
         &mut self,
         id: &str,
-        file_url: &url::Url,
-        filename: &str,
-        base_dir: &std::path::Path,
+        torrent: &ResolvedTorrent,
+        output_dir: &std::path::Path,
+        only_files: Option<Vec<usize>>,
     ) -> Result<()>;
 
-    async fn wait_until_finished(&mut self, id: &str) -> Result<()>;
+    async fn list_files(&self, source: &TorrentSource) -> Result<Vec<app_lib::torrent::TorrentFile>>;
+
+    fn get_files(&self, source_id: &str) -> Result<Vec<app_lib::torrent::TorrentFile>>;
+
+    async fn update_files(&mut self, source_id: &str, only_files: Vec<usize>) -> Result<()>;
+
+    fn wait_until_finished(&self, id: &str) -> Result<futures::future::BoxFuture<'static, Result<()>>>;
 
     fn get_stats_receiver(&self, id: &str) -> Option<tokio::sync::watch::Receiver<app_lib::torrent::TorrentStats> >;
 
@@ -33,6 +58,22 @@ mock! {
     async fn toggle_pause(&mut self, source_id: &str) -> Result<()>;
 
     async fn remove_torrent(&mut self, source_id: &str) -> Result<()>;
+
+    fn set_global_limits(&mut self, limits: app_lib::torrent::BandwidthLimits);
+
+    async fn set_torrent_limits(&mut self, source_id: &str, limits: app_lib::torrent::BandwidthLimits) -> Result<()>;
+
+    fn set_global_seeding_policy(&mut self, policy: app_lib::torrent::SeedingPolicy);
+
+    fn set_torrent_seeding_policy(&mut self, source_id: &str, policy: Option<app_lib::torrent::SeedingPolicy>);
+
+    async fn enforce_seeding_policies(&mut self) -> Vec<String>;
+
+    fn broken_torrents(&self) -> Vec<app_lib::torrent::BrokenTorrent>;
+
+    async fn repair_torrent(&mut self, torrent_id: usize, source_id: &str, output_dir: &std::path::Path) -> Result<()>;
+
+    async fn forget_torrent(&mut self, torrent_id: usize) -> Result<()>;
     }
 }
 
@@ -41,20 +82,16 @@ mock! {
 async fn test_e2e_download() {
     let dir = TempDir::new("test").unwrap();
     let session = Session::new(dir.path().to_path_buf()).await.unwrap();
-    let client = reqwest::Client::new();
+    let client = HttpClient::new(reqwest::Client::new());
 
     let rqbit = Arc::new(Mutex::new(
         RqbitService::new(session, client.clone(), dir.path()).await,
     ));
     let nyaa = Nyaa::new(rqbit.clone(), client);
 
-    nyaa.download("1990813", dir.path()).await.unwrap();
-    rqbit
-        .lock()
-        .await
-        .wait_until_finished("1990813")
-        .await
-        .unwrap();
+    nyaa.download("1990813", dir.path(), None).await.unwrap();
+    let finished = rqbit.lock().await.wait_until_finished("1990813").unwrap();
+    finished.await.unwrap();
 
     let library = read_dir(dir.path())
         .unwrap()
@@ -72,17 +109,22 @@ async fn test_e2e_download() {
         .collect::<Result<Vec<String>, io::Error>>()
         .unwrap();
 
-    assert_eq!(downloaded_files.len(), 2);
+    // only the downloaded content, the .torrent file is no longer written to disk
+    assert_eq!(downloaded_files.len(), 1);
 }
 
 #[tokio::test(flavor = "multi_thread")]
 #[ignore]
 async fn test_e2e_search() {
-    let client = reqwest::Client::new();
+    let client = HttpClient::new(reqwest::Client::new());
     let rqbit = MockTorrentService::new();
 
     let nyaa = Nyaa::new(Arc::new(Mutex::new(rqbit)), client);
-    let (results, pagination) = nyaa.search("c=3_0").await.unwrap();
+    let query = SearchQuery {
+        category: Some(NyaaCategory::Literature(LiteratureSubCategory::Base)),
+        ..Default::default()
+    };
+    let (results, pagination) = nyaa.search(&query).await.unwrap();
     assert_eq!(75, results.len());
     assert_eq!(
         pagination,
@@ -94,3 +136,337 @@ async fn test_e2e_search() {
         }
     )
 }
+
+fn offline_nyaa(stand_in: &NyaaStandIn, torrent_service: MockTorrentService) -> Nyaa {
+    Nyaa::new(
+        Arc::new(Mutex::new(torrent_service)),
+        HttpClient::new(reqwest::Client::new()),
+    )
+    .with_base_url(stand_in.url.clone())
+}
+
+fn literature_query(page: Option<u32>) -> SearchQuery {
+    SearchQuery {
+        category: Some(NyaaCategory::Literature(
+            LiteratureSubCategory::EnglishTranslated,
+        )),
+        page,
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn test_search() {
+    let stand_in = NyaaStandIn::start().await;
+    let nyaa = offline_nyaa(&stand_in, MockTorrentService::new());
+
+    let (results, pagination) = nyaa.search(&literature_query(None)).await.unwrap();
+
+    assert_eq!(results.len(), 3);
+    assert_eq!(
+        pagination,
+        PaginationInfo {
+            min_page: 1,
+            max_page: 2,
+            has_prev: false,
+            has_next: true,
+        }
+    );
+
+    let info = &results[0];
+    assert_eq!(info.id, "1990813");
+    assert_eq!(
+        info.title,
+        "My Quiet Blacksmith Life in Another World v05 (2025) (Digital) (Ushi)"
+    );
+    assert!(matches!(info.category, Category::Manga));
+    assert_eq!(info.size.unit, SizeUnit::MiB);
+    assert_eq!(info.size.bytes, 253021389);
+    assert_eq!(info.timestamp.timestamp(), 1752157351);
+    assert_eq!((info.seeders, info.leechers, info.completed), (42, 3, 512));
+    assert_eq!(info.info_hash, INFO_HASH);
+
+    let statuses: Vec<ReleaseStatus> = results.iter().map(|info| info.status).collect();
+    assert_eq!(
+        statuses,
+        vec![
+            ReleaseStatus::Trusted,
+            ReleaseStatus::Remake,
+            ReleaseStatus::Normal
+        ]
+    );
+
+    let requests = stand_in.requests();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].query(), Some("c=3_1&q="));
+}
+
+#[tokio::test]
+async fn test_search_last_page() {
+    let stand_in = NyaaStandIn::start().await;
+    let nyaa = offline_nyaa(&stand_in, MockTorrentService::new());
+
+    let (results, pagination) = nyaa.search(&literature_query(Some(2))).await.unwrap();
+
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].id, "1990700");
+    assert_eq!(results[0].size.bytes, 512 * 1024);
+    assert_eq!(
+        pagination,
+        PaginationInfo {
+            min_page: 1,
+            max_page: 2,
+            has_prev: true,
+            has_next: false,
+        }
+    );
+}
+
+#[tokio::test]
+async fn test_search_uploader() {
+    let stand_in = NyaaStandIn::start().await;
+    let nyaa = offline_nyaa(&stand_in, MockTorrentService::new());
+
+    let query = SearchQuery {
+        uploader: Some("Ushi".to_owned()),
+        ..literature_query(Some(2))
+    };
+    let (results, pagination) = nyaa.search(&query).await.unwrap();
+
+    assert_eq!(results.len(), 1);
+    assert!(pagination.has_prev);
+
+    let requests = stand_in.requests();
+    assert_eq!(requests[0].path(), "/user/Ushi");
+    assert_eq!(requests[0].query(), Some("c=3_1&q=&p=2"));
+}
+
+#[tokio::test]
+async fn test_search_rss() {
+    let stand_in = NyaaStandIn::start().await;
+    let nyaa = offline_nyaa(&stand_in, MockTorrentService::new()).with_backend(NyaaBackend::Rss);
+
+    let (results, pagination) = nyaa.search(&literature_query(None)).await.unwrap();
+
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].id, "1990813");
+    assert_eq!(results[0].info_hash, INFO_HASH);
+    assert_eq!(
+        pagination,
+        PaginationInfo {
+            min_page: 1,
+            max_page: 1,
+            has_prev: false,
+            has_next: false,
+        }
+    );
+    assert_eq!(stand_in.requests()[0].query(), Some("c=3_1&q=&page=rss"));
+}
+
+#[tokio::test]
+async fn test_search_without_results() {
+    let stand_in = NyaaStandIn::start().await;
+    let nyaa = offline_nyaa(&stand_in, MockTorrentService::new());
+
+    let query = SearchQuery {
+        text: "empty".to_owned(),
+        ..literature_query(None)
+    };
+    let (results, _) = nyaa.search(&query).await.unwrap();
+
+    // nothing matching is a valid answer, so the rss feed isn't asked as well
+    assert!(results.is_empty());
+    assert_eq!(stand_in.requests().len(), 1);
+}
+
+#[tokio::test]
+async fn test_search_falls_back_to_rss() {
+    let stand_in = NyaaStandIn::start().await;
+    let nyaa = offline_nyaa(&stand_in, MockTorrentService::new());
+
+    let query = SearchQuery {
+        text: "unparseable".to_owned(),
+        ..literature_query(None)
+    };
+    let (results, _) = nyaa.search(&query).await.unwrap();
+
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].id, "1990813");
+    let requests = stand_in.requests();
+    assert_eq!(requests.len(), 2);
+    assert_eq!(requests[1].query(), Some("c=3_1&q=unparseable&page=rss"));
+}
+
+#[tokio::test]
+async fn test_get_info_by_id() {
+    let stand_in = NyaaStandIn::start().await;
+    let nyaa = offline_nyaa(&stand_in, MockTorrentService::new());
+
+    let info = nyaa.get_info_by_id("1990813").await.unwrap();
+    assert_eq!(info.id, "1990813");
+    assert_eq!(
+        info.title,
+        "My Quiet Blacksmith Life in Another World v05 (2025) (Digital) (Ushi)"
+    );
+    assert_eq!(info.info_hash, INFO_HASH);
+    assert_eq!(info.status, ReleaseStatus::Trusted);
+    assert_eq!(stand_in.requests()[0].path(), "/view/1990813");
+
+    // unknown ids are a 404 on the site
+    assert!(nyaa.get_info_by_id("404").await.is_err());
+}
+
+/// A real torrent service that can't reach any peers, so magnets never resolve
+async fn offline_rqbit(dir: &TempDir) -> RqbitService {
+    let session = Session::new_with_opts(
+        dir.path().to_path_buf(),
+        SessionOptions {
+            disable_dht: true,
+            disable_dht_persistence: true,
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+    RqbitService::new(
+        session,
+        HttpClient::new(reqwest::Client::new()),
+        &dir.path().join("session.json"),
+    )
+    .await
+    .with_magnet_timeout(Duration::from_millis(500))
+}
+
+/// The single file in the recorded torrent
+fn recorded_torrent_file(selected: bool) -> TorrentFile {
+    TorrentFile {
+        index: 0,
+        path: format!("{}.cbz", TITLE),
+        size: 7,
+        selected,
+    }
+}
+
+#[tokio::test]
+async fn test_download_falls_back_to_torrent_file() {
+    let stand_in = NyaaStandIn::start().await;
+    let dir = TempDir::new("test").unwrap();
+    let rqbit = Arc::new(Mutex::new(offline_rqbit(&dir).await));
+    let nyaa = Nyaa::new(rqbit.clone(), HttpClient::new(reqwest::Client::new()))
+        .with_base_url(stand_in.url.clone());
+
+    let output_dir = tokio::time::timeout(
+        Duration::from_secs(10),
+        nyaa.download("1990813", dir.path(), Some(vec![0])),
+    )
+    .await
+    .expect("the magnet timeout to stop waiting for peers")
+    .unwrap();
+    assert_eq!(output_dir, dir.path().join(TITLE));
+
+    // the magnet can't resolve offline, so the torrent was added from the recorded file
+    let requests = stand_in.requests();
+    assert_eq!(requests.last().unwrap().path(), "/download/1990813.torrent");
+    let mut rqbit = rqbit.lock().await;
+    assert_eq!(
+        rqbit.get_files("1990813").unwrap(),
+        vec![recorded_torrent_file(true)]
+    );
+    rqbit.remove_torrent("1990813").await.unwrap();
+}
+
+#[tokio::test]
+async fn test_queued_download_can_write_metafile() {
+    let stand_in = NyaaStandIn::start().await;
+    let dir = TempDir::new("test").unwrap();
+    let queue = Arc::new(Mutex::new(
+        DownloadQueue::new(Box::new(offline_rqbit(&dir).await)).with_max_active(1),
+    ));
+    let torrent = ResolvedTorrent {
+        torrent_bytes: std::fs::read(fixture_path("1990813.torrent")).unwrap(),
+    };
+    queue
+        .lock()
+        .await
+        .download_torrent("active", &torrent, &dir.path().join("active"), None)
+        .await
+        .unwrap();
+    let nyaa = Nyaa::new(queue.clone(), HttpClient::new(reqwest::Client::new()))
+        .with_base_url(stand_in.url.clone());
+
+    let output_dir = tokio::time::timeout(
+        Duration::from_secs(10),
+        nyaa.download("1990813", dir.path(), None),
+    )
+    .await
+    .unwrap()
+    .unwrap();
+
+    // the queue is full, so the download waits without the torrent service creating its folder
+    let mut queue = queue.lock().await;
+    let stats = queue.get_stats_receiver("1990813").unwrap();
+    assert_eq!(stats.borrow().queue_position(), Some(0));
+    let source = SourceMeta {
+        id: "1990813".to_owned(),
+        provider: Sources::Nyaa,
+    };
+    Metafile::new(source, None)
+        .write(&output_dir)
+        .await
+        .unwrap();
+    assert!(output_dir.join(".meta").is_file());
+    queue.remove_torrent("active").await.unwrap();
+}
+
+#[tokio::test]
+async fn test_list_files() {
+    let stand_in = NyaaStandIn::start().await;
+    let dir = TempDir::new("test").unwrap();
+    let nyaa = Nyaa::new(
+        Arc::new(Mutex::new(offline_rqbit(&dir).await)),
+        HttpClient::new(reqwest::Client::new()),
+    )
+    .with_base_url(stand_in.url.clone());
+
+    let files = nyaa.list_files("1990813").await.unwrap();
+
+    assert_eq!(files, vec![recorded_torrent_file(true)]);
+    assert_eq!(stand_in.requests()[0].path(), "/download/1990813.torrent");
+}
+
+#[tokio::test]
+async fn test_cached_responses() {
+    let stand_in = NyaaStandIn::start().await;
+    let cache = Arc::new(ResponseCache::new(Duration::from_secs(60), 10));
+    let nyaa = offline_nyaa(&stand_in, MockTorrentService::new()).with_cache(cache);
+
+    nyaa.get_info_by_id("1990813").await.unwrap();
+    nyaa.get_info_by_id("1990813").await.unwrap();
+    assert_eq!(stand_in.requests().len(), 1);
+
+    let mut query = literature_query(None);
+    nyaa.search(&query).await.unwrap();
+    nyaa.search(&query).await.unwrap();
+    assert_eq!(stand_in.requests().len(), 2);
+
+    // refreshing skips the cache, and the fresh response is cached again
+    query.refresh = true;
+    nyaa.search(&query).await.unwrap();
+    assert_eq!(stand_in.requests().len(), 3);
+    query.refresh = false;
+    nyaa.search(&query).await.unwrap();
+    assert_eq!(stand_in.requests().len(), 3);
+}
+
+#[tokio::test]
+async fn test_view_pages_use_their_own_ttl() {
+    let stand_in = NyaaStandIn::start().await;
+    let cache = Arc::new(ResponseCache::new(Duration::from_secs(60), 10));
+    let nyaa = offline_nyaa(&stand_in, MockTorrentService::new())
+        .with_cache(cache)
+        .with_view_ttl(Duration::ZERO);
+
+    nyaa.get_info_by_id("1990813").await.unwrap();
+    nyaa.get_info_by_id("1990813").await.unwrap();
+    assert_eq!(stand_in.requests().len(), 2);
+}
@@ -14,6 +14,7 @@ import {
 } from "@/components/ui/pagination";
 import { Spinner } from "@/components/ui/spinner";
 import { PaginationInfo } from "@/types/PaginationInfo";
+import { SearchQuery } from "@/types/SearchQuery";
 import { SearchResponse, SearchResult } from "@/types/SearchResult";
 import { invoke } from "@tauri-apps/api/core";
 import { useEffect, useState } from "react";
@@ -23,9 +24,12 @@ export default function Browse() {
   const [loading, setLoading] = useState(true);
   const [page, setPage] = useState(1);
   const [paginationInfo, setPaginationInfo] = useState<PaginationInfo>();
-  const [query, setQuery] = useState("c=3_1");
+  const [query, setQuery] = useState<SearchQuery>({
+    text: "",
+    category: "3_1",
+  });
 
-  const search = async (query: string) => {
+  const search = async (query: SearchQuery) => {
     setLoading(true);
     const { search_results: results, pagination } =
       await invoke<SearchResponse>("search", {
@@ -37,7 +41,7 @@ export default function Browse() {
   };
 
   useEffect(() => {
-    search(`${query}&p=${page}`);
+    search({ ...query, page });
   }, [page, query]);
 
   // shoutout gemini for generating this.
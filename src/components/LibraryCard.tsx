@@ -9,7 +9,7 @@ import {
   DropdownMenuTrigger,
 } from "./ui/dropdown-menu";
 import { useDownloads } from "./providers/DownloadsProvider";
-import { cn } from "@/lib/utils";
+import { cn, releaseSummary } from "@/lib/utils";
 
 export const LibraryCard = ({
   libraryEntry,
@@ -22,6 +22,7 @@ export const LibraryCard = ({
 }) => {
   const {
     name,
+    release,
     metafile: {
       source: { id },
       metadata,
@@ -59,6 +60,9 @@ export const LibraryCard = ({
         )}
         <div className="p-2 mt-2 text-center">
           <h1>{name}</h1>
+          <p className="text-sm text-muted-foreground">
+            {releaseSummary(release)}
+          </p>
         </div>
       </div>
       {!isDownloading && (
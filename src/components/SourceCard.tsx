@@ -13,9 +13,12 @@ import { ArrowDown, ArrowUp, Book, Check, Download } from "lucide-react";
 import { Button } from "./ui/button";
 import { invoke } from "@tauri-apps/api/core";
 import { useDownloads } from "./providers/DownloadsProvider";
+import { releaseSummary } from "@/lib/utils";
 
 export const SourceCard = ({
   searchResult: {
+    provider,
+    release,
     media_info: {
       id,
       title,
@@ -25,6 +28,7 @@ export const SourceCard = ({
       leechers,
       completed,
       timestamp,
+      status,
     },
     metadata,
   },
@@ -37,7 +41,10 @@ export const SourceCard = ({
   return (
     <Card>
       <CardHeader className="h-4">
-        <CardDescription>{category}</CardDescription>
+        <CardDescription>
+          {category}
+          {status !== "Normal" && ` · ${status}`}
+        </CardDescription>
       </CardHeader>
       <CardContent className="flex flex-col h-full">
         {metadata && metadata.cover ? (
@@ -53,6 +60,7 @@ export const SourceCard = ({
           <Book className="flex-1 w-full h-full" size={260} />
         )}
         <CardTitle className="mt-4 mb-2">{title}</CardTitle>
+        <CardDescription>{releaseSummary(release)}</CardDescription>
         <CardDescription className="flex gap-1">
           <div className="flex items-center">
             {seeders} <ArrowUp size={16} />
@@ -78,7 +86,7 @@ export const SourceCard = ({
             className="cursor-pointer disabled:cursor-not-allowed"
             variant="secondary"
             onClick={() => {
-              invoke("download", { id });
+              invoke("download", { id, provider });
             }}
           >
             <Download />
@@ -14,6 +14,7 @@ import {
 import { optionsToQueryMap } from "@/lib/sourceOptionsToQuery";
 import { Button } from "./ui/button";
 import { Search } from "lucide-react";
+import { SearchQuery } from "@/types/SearchQuery";
 
 type SourceOption = {
   name: string;
@@ -26,27 +27,38 @@ const sourceToOptions: { [source: string]: SourceOption[] } = {
     {
       name: "Filter",
       options: [
-        { name: "No Filter", value: "0" },
-        { name: "No Remakes", value: "1" },
-        { name: "Trusted Only", value: "2" },
+        { name: "No Filter", value: "NoFilter" },
+        { name: "No Remakes", value: "NoRemakes" },
+        { name: "Trusted Only", value: "TrustedOnly" },
       ],
     },
     {
       name: "Category",
       options: [
-        // { name: "All Categories", value: "0_0" },
-        // { name: "Anime", value: "1_0" },
-        // { name: "Anime - Music Video", value: "1_1" },
-        // { name: "Anime - English Translated", value: "1_2" },
-        // { name: "Anime - Non-English Translated", value: "1_3" },
-        // { name: "Anime - Raw", value: "1_4" },
-        // { name: "Audio", value: "2_0" },
-        // { name: "Audio - Lossless", value: "2_1" },
-        // { name: "Audio - Lossy", value: "2_2" },
+        { name: "All Categories", value: "0_0" },
+        { name: "Anime", value: "1_0" },
+        { name: "Anime - Music Video", value: "1_1" },
+        { name: "Anime - English Translated", value: "1_2" },
+        { name: "Anime - Non-English Translated", value: "1_3" },
+        { name: "Anime - Raw", value: "1_4" },
+        { name: "Audio", value: "2_0" },
+        { name: "Audio - Lossless", value: "2_1" },
+        { name: "Audio - Lossy", value: "2_2" },
         { name: "Literature", value: "3_0" },
         { name: "Literature - English Translated", value: "3_1" },
         { name: "Literature - Non-English Translated", value: "3_2" },
         { name: "Literature - Raw", value: "3_3" },
+        { name: "Live Action", value: "4_0" },
+        { name: "Live Action - English Translated", value: "4_1" },
+        { name: "Live Action - Idol/Promotional Video", value: "4_2" },
+        { name: "Live Action - Non-English Translated", value: "4_3" },
+        { name: "Live Action - Raw", value: "4_4" },
+        { name: "Pictures", value: "5_0" },
+        { name: "Pictures - Graphics", value: "5_1" },
+        { name: "Pictures - Photos", value: "5_2" },
+        { name: "Software", value: "6_0" },
+        { name: "Software - Applications", value: "6_1" },
+        { name: "Software - Games", value: "6_2" },
       ],
       defaultValue: "3_1",
     },
@@ -56,7 +68,7 @@ const sourceToOptions: { [source: string]: SourceOption[] } = {
 export const SourceSearch = ({
   setQueryAction,
 }: {
-  setQueryAction: (q: string) => void;
+  setQueryAction: (q: SearchQuery) => void;
 }) => {
   const [source, setSource] = useState("Nyaa");
   const sourceOptions = sourceToOptions[source] ?? [];
@@ -73,8 +85,7 @@ export const SourceSearch = ({
         >;
 
         const converter = optionsToQueryMap[source];
-        const queryString = converter(data);
-        setQueryAction(queryString);
+        setQueryAction(converter(data));
       }}
     >
       <Select name="source" value={source} onValueChange={setSource}>
@@ -14,14 +14,21 @@ import { toast } from "sonner";
 export type DownloadInfo = {
   id: string;
   name: string | null;
-  state: "initializing" | "live" | "paused" | "error";
+  state: "Queued" | "initializing" | "live" | "paused" | "error";
   progress_bytes: number;
   uploaded_bytes: number;
   total_bytes: number;
   finished: boolean;
+  // uploaded bytes over the size of the torrent
+  ratio: number;
   upload_speed: number | null;
   download_speed: number | null;
   remaining_time: string | null;
+  // limits in effect for this download in bytes per second, null when unlimited
+  upload_limit_bps: number | null;
+  download_limit_bps: number | null;
+  // place in the download queue, 0 starts next, null once the download has started
+  queue_position: number | null;
 };
 
 type DownloadsContextType = {
@@ -64,6 +71,8 @@ export function DownloadsProvider({ children }: { children: ReactNode }) {
           upload_speed: null,
           download_speed: null,
           remaining_time: null,
+          upload_limit_bps: null,
+          download_limit_bps: null,
         },
       }));
     });
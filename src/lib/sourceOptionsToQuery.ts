@@ -1,18 +1,18 @@
-export const nyaaOptionsToQuery = (data: Record<string, string>) => {
-  const components = [];
+import { NyaaFilter, SearchQuery } from "@/types/SearchQuery";
+
+export const nyaaOptionsToQuery = (
+  data: Record<string, string>,
+): SearchQuery => {
+  const query: SearchQuery = { text: data["query"] ?? "" };
   if (data["Filter"]) {
-    components.push(`f=${data["Filter"]}`);
+    query.filter = data["Filter"] as NyaaFilter;
   }
 
   if (data["Category"]) {
-    components.push(`c=${data["Category"]}`);
-  }
-
-  if (data["query"]) {
-    components.push(`q=${data["query"].replaceAll(" ", "+")}`);
+    query.category = data["Category"];
   }
 
-  return components.join("&");
+  return query;
 };
 
 export const optionsToQueryMap: Record<string, typeof nyaaOptionsToQuery> = {
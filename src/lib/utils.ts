@@ -1,5 +1,6 @@
 import { clsx, type ClassValue } from "clsx";
 import { twMerge } from "tailwind-merge";
+import { NumberRange, ParsedRelease } from "@/types/ParsedRelease";
 
 export function cn(...inputs: ClassValue[]) {
   return twMerge(clsx(inputs));
@@ -18,3 +19,25 @@ export const bytesToString = (bytes: number) => {
 
   return `${bytes} B`;
 };
+
+const rangeToString = (prefix: string, { start, end }: NumberRange) => {
+  const pad = (n: number) => n.toString().padStart(2, "0");
+  return start === end
+    ? `${prefix}${pad(start)}`
+    : `${prefix}${pad(start)}-${pad(end)}`;
+};
+
+// e.g. "v01-05 by Oak (Digital)"
+export const releaseSummary = ({
+  volumes,
+  chapters,
+  group,
+  flags,
+}: ParsedRelease) => {
+  const parts = [];
+  if (volumes) parts.push(rangeToString("v", volumes));
+  if (chapters) parts.push(rangeToString("c", chapters));
+  if (group) parts.push(`by ${group}`);
+  parts.push(...flags.map((flag) => `(${flag})`));
+  return parts.join(" ");
+};
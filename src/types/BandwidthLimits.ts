@@ -0,0 +1,11 @@
+// transfer rate limits in bytes per second, null when unlimited
+export type BandwidthLimits = {
+  upload_bps: number | null;
+  download_bps: number | null;
+};
+
+export type BandwidthSettings = {
+  global: BandwidthLimits;
+  // overrides keyed by source id, applied on top of the global limits
+  torrents: Record<string, BandwidthLimits>;
+};
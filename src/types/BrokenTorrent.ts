@@ -0,0 +1,25 @@
+// why a torrent from the last session could not be matched to a download
+export type RestoreProblem =
+  | { type: "MissingFolder" }
+  | { type: "UnreadableMetafile"; error: string }
+  // the session has the torrent but the session store doesn't
+  | { type: "Orphaned" };
+
+// a torrent from the last session that could not be restored
+export type BrokenTorrent = {
+  torrent_id: number;
+  name: string;
+  // null for orphaned torrents
+  output_dir: string | null;
+  problem: RestoreProblem;
+  // comment of the torrent file, sites tend to link back to the upload in it
+  comment: string | null;
+};
+
+export type RepairAction =
+  // attach the torrent to an existing library entry and download into its folder
+  | { type: "Relink"; source_id: string }
+  // make a new library entry for the torrent, local content unless its comment links to the upload
+  | { type: "Recreate" }
+  // remove the torrent from the session, leaving its files in place
+  | { type: "Forget" };
@@ -0,0 +1,33 @@
+import { SearchQuery } from "./SearchQuery";
+import { Category, MediaInfo, Sources } from "./SourceInfo";
+
+export type RuleDownload = {
+  id: string;
+  provider: Sources;
+  title: string;
+  downloaded_at: string;
+};
+
+export type DownloadRule = {
+  id: string;
+  name: string;
+  enabled: boolean;
+  query: SearchQuery;
+  // case insensitive regex matched against the release title
+  title_pattern: string | null;
+  min_seeders: number | null;
+  category: Category | null;
+  trusted_only: boolean;
+  // size bounds in bytes, inclusive
+  min_size: number | null;
+  max_size: number | null;
+  history: RuleDownload[];
+  // ids of results that were already there when the rule was added, never downloaded
+  seen_ids: string[];
+};
+
+// result returned by "dry_run_download_rule"
+export type RuleMatch = {
+  provider: Sources;
+  media_info: MediaInfo;
+};
@@ -0,0 +1,7 @@
+import { Sources } from "./SourceInfo";
+
+export type FavouriteUploader = {
+  name: string;
+  provider: Sources;
+  added_at: string;
+};
@@ -1,4 +1,5 @@
 import { Metadata } from "./Metadata";
+import { ParsedRelease } from "./ParsedRelease";
 import { SourceMeta } from "./SourceInfo";
 
 type Metafile = {
@@ -28,7 +29,20 @@ export enum ReaderLayout {
 
 export type LibraryEntry = {
   name: string;
+  release: ParsedRelease;
   metafile: Metafile;
   output_dir: string;
   files: string[];
 };
+
+// an archive in a library entry that could not be read
+export type InvalidArchive = {
+  file: string;
+  error: string;
+};
+
+// payload of the download-finished event
+export type FinishedDownload = {
+  entry: LibraryEntry;
+  invalid_archives: InvalidArchive[];
+};
@@ -0,0 +1,16 @@
+export type NumberRange = {
+  start: number;
+  end: number;
+};
+
+export type ReleaseFlag = "Digital" | "Completed" | "Audiobook" | "Raw";
+
+export type ParsedRelease = {
+  title: string;
+  alt_titles: string[];
+  volumes: NumberRange | null;
+  chapters: NumberRange | null;
+  years: NumberRange | null;
+  group: string | null;
+  flags: ReleaseFlag[];
+};
@@ -0,0 +1,17 @@
+import { SearchQuery } from "./SearchQuery";
+import { MediaInfo } from "./SourceInfo";
+
+export type SavedSearch = {
+  id: string;
+  name: string;
+  query: SearchQuery;
+  seen_ids: string[];
+  last_checked: string | null;
+};
+
+// payload of the "new-releases" event
+export type NewReleases = {
+  search_id: string;
+  name: string;
+  releases: MediaInfo[];
+};
@@ -0,0 +1,26 @@
+import { Sources } from "./SourceInfo";
+
+export type NyaaFilter = "NoFilter" | "NoRemakes" | "TrustedOnly";
+
+export type SortField =
+  | "Date"
+  | "Size"
+  | "Comments"
+  | "Seeders"
+  | "Leechers"
+  | "Downloads";
+
+export type SortOrder = "Ascending" | "Descending";
+
+export type SearchQuery = {
+  text: string;
+  // category in nyaa query param format e.g. "3_1"
+  category?: string;
+  filter?: NyaaFilter;
+  sort?: SortField;
+  order?: SortOrder;
+  page?: number;
+  uploader?: string;
+  // searches every source when missing
+  source?: Sources;
+};
@@ -1,8 +1,11 @@
 import { Metadata } from "./Metadata";
 import { PaginationInfo } from "./PaginationInfo";
-import { MediaInfo } from "./SourceInfo";
+import { NumberRange, ParsedRelease } from "./ParsedRelease";
+import { MediaInfo, Sources } from "./SourceInfo";
 
 export type SearchResult = {
+  provider: Sources;
+  release: ParsedRelease;
   media_info: MediaInfo;
   metadata?: Metadata;
 };
@@ -11,3 +14,18 @@ export type SearchResponse = {
   search_results: SearchResult[];
   pagination: PaginationInfo;
 };
+
+// releases of the same series, returned by "search_grouped"
+export type SearchGroup = {
+  title: string;
+  metadata?: Metadata;
+  volumes: NumberRange[];
+  // index into releases of the release with the most seeders
+  best_release: number;
+  releases: SearchResult[];
+};
+
+export type GroupedSearchResponse = {
+  groups: SearchGroup[];
+  pagination: PaginationInfo;
+};
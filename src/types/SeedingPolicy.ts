@@ -0,0 +1,14 @@
+// when a finished torrent stops seeding
+export type SeedingPolicy =
+  | { type: "Unlimited" }
+  // seed until ratio times the size of the torrent has been uploaded
+  | { type: "Ratio"; ratio: number }
+  // seed for secs seconds after finishing
+  | { type: "Duration"; secs: number }
+  | { type: "Never" };
+
+export type SeedingSettings = {
+  global: SeedingPolicy;
+  // overrides keyed by source id, used instead of the global policy
+  torrents: Record<string, SeedingPolicy>;
+};
@@ -1,10 +1,20 @@
-export type Category = "Manga";
+export type Category =
+  | "All"
+  | "Anime"
+  | "Audio"
+  | "Manga"
+  | "LiveAction"
+  | "Pictures"
+  | "Software";
 
 export type FileSize = {
-  unit: "MiB" | "GiB";
+  unit: "Bytes" | "KiB" | "MiB" | "GiB" | "TiB";
   size: number;
+  bytes: number;
 };
 
+export type ReleaseStatus = "Normal" | "Trusted" | "Remake" | "Batch";
+
 export type MediaInfo = {
   id: string;
   category: Category;
@@ -14,9 +24,13 @@ export type MediaInfo = {
   seeders: number;
   leechers: number;
   completed: number;
+  info_hash: string;
+  status: ReleaseStatus;
 };
 
+export type Sources = "Nyaa" | "Local";
+
 export type SourceMeta = {
   id: string;
-  provider: string;
+  provider: Sources;
 };
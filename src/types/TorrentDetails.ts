@@ -0,0 +1,20 @@
+import { FileSize, MediaInfo } from "./SourceInfo";
+
+export type FileTreeNode =
+  | { type: "Folder"; name: string; children: FileTreeNode[] }
+  | { type: "File"; name: string; size: FileSize | null };
+
+export type Comment = {
+  author: string;
+  timestamp: string;
+  content: string;
+};
+
+export type TorrentDetails = {
+  info: MediaInfo;
+  submitter: string | null;
+  website: string | null;
+  description: string;
+  files: FileTreeNode[];
+  comments: Comment[];
+};
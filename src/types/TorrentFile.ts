@@ -0,0 +1,7 @@
+// a file inside a torrent, index is its position in the torrent's file list
+export type TorrentFile = {
+  index: number;
+  path: string;
+  size: number;
+  selected: boolean;
+};